@@ -0,0 +1,299 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::SageError;
+use crate::git::get_git_status;
+use crate::manifest::{Manifest, SAGE_DIRECTORY_NAME};
+use crate::project::ProjectConfig;
+use crate::template::SECRET_VALUE_MASK;
+use crate::terminal::print_warning;
+use crate::terraform::CommandContext;
+
+pub const AUDIT_LOG_FILE_NAME: &'static str = "audit.log";
+pub const UNKNOWN_VALUE: &'static str = "unknown";
+// Arguments with the values, which may contain secrets.
+pub const REDACTED_ARGUMENTS: &'static [&str; 2] = &["-var", "-backend-config"];
+
+// Settings of the audit log in the project settings.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    pub path: Option<String>,
+}
+
+// A single invocation of Terraform, stored as a line of the audit log.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditRecord {
+    pub timestamp: String,
+    pub user: String,
+    pub hostname: String,
+    pub git_commit: Option<String>,
+    pub git_dirty: Option<bool>,
+    pub config: String,
+    pub directory: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub template_hash: Option<String>,
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+}
+
+// Returns the path to the audit log of the project in the directory specified
+// in `directory` parameter. By default the log is stored in the `.sage`
+// directory, another path can be set in the `[audit]` section of the project
+// settings, relative to the project directory.
+pub fn get_audit_log_path(directory: &String) -> Result<String, SageError> {
+    let project = ProjectConfig::load(directory)?;
+    let path = match project.audit.path {
+        Some(path) => Path::new(directory).join(path),
+        None => Path::new(directory)
+            .join(SAGE_DIRECTORY_NAME)
+            .join(AUDIT_LOG_FILE_NAME),
+    };
+    Ok(path.to_string_lossy().into_owned())
+}
+
+// Appends the record about Terraform's command to the audit log of the
+// project. The project, the configuration and the generated main module are
+// taken from the `context` of the command. Failures are reported as warnings,
+// so that they don't interrupt the command.
+pub fn record_invocation(
+    command: &str,
+    args: &Vec<String>,
+    context: &CommandContext,
+    exit_code: Option<i32>,
+    duration: Duration,
+) {
+    let directory = fs::canonicalize(&context.directory)
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| context.directory.clone());
+    let config = context.config.clone();
+
+    let git_status = get_git_status(&directory);
+    let template_hash = context.module.as_ref().and_then(|module| {
+        Manifest::load_for(module)
+            .get(module)
+            .filter(|entry| entry.config == config)
+            .map(|entry| entry.hash.clone())
+    });
+    let record = AuditRecord {
+        timestamp: format_timestamp(SystemTime::now()),
+        user: get_user_name(),
+        hostname: get_hostname(),
+        git_commit: git_status.as_ref().map(|status| status.commit.clone()),
+        git_dirty: git_status.as_ref().map(|status| status.dirty),
+        config,
+        directory: directory.clone(),
+        command: command.to_string(),
+        args: redact_arguments(args),
+        template_hash,
+        exit_code,
+        duration_ms: duration.as_millis() as u64,
+    };
+
+    let result = get_audit_log_path(&directory).and_then(|path| {
+        append_record(&path, &record).map_err(|err| SageError::Io(err, path.clone()))
+    });
+    if let Err(err) = result {
+        print_warning(&format!("Unable to write the audit log: {}", err));
+    }
+}
+
+// Appends the record to the audit log as a line in JSON format.
+fn append_record(path: &String, record: &AuditRecord) -> io::Result<()> {
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let line = serde_json::to_string(record)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)
+}
+
+// Reads all records from the audit log by path in `path` parameter. If the
+// log doesn't exist yet, then returns an empty list.
+pub fn read_audit_log(path: &String) -> Result<Vec<AuditRecord>, SageError> {
+    let raw_data = match fs::read_to_string(path) {
+        Ok(raw_data) => raw_data,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(SageError::Io(err, path.clone())),
+    };
+
+    raw_data
+        .lines()
+        .enumerate()
+        .filter(|(_index, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).map_err(|err| {
+                let message = format!("The line {} of {} is malformed: {}.", index + 1, path, err);
+                SageError::InvalidConfig(message)
+            })
+        })
+        .collect()
+}
+
+// Masks the values of the variables and the backend settings, passed in the
+// arguments, e.g. `-var=password=secret` becomes `-var=password=********`.
+pub fn redact_arguments(args: &Vec<String>) -> Vec<String> {
+    let redact_value = |assignment: &str| match assignment.split_once('=') {
+        Some((name, _value)) => format!("{}={}", name, SECRET_VALUE_MASK),
+        None => assignment.to_string(),
+    };
+
+    let mut redacted = Vec::new();
+    let mut is_value = false;
+    for arg in args.iter() {
+        let value = match arg.split_once('=') {
+            _ if is_value => redact_value(arg),
+            Some((name, assignment)) if REDACTED_ARGUMENTS.contains(&name) => {
+                format!("{}={}", name, redact_value(assignment))
+            }
+            _ => arg.clone(),
+        };
+        is_value = REDACTED_ARGUMENTS.contains(&arg.as_str());
+        redacted.push(value);
+    }
+    redacted
+}
+
+// Returns the name of the current user.
+fn get_user_name() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| UNKNOWN_VALUE.to_string())
+}
+
+// Returns the name of the current host.
+fn get_hostname() -> String {
+    Command::new("hostname")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|hostname| !hostname.is_empty())
+        .or_else(|| env::var("HOSTNAME").ok())
+        .or_else(|| env::var("COMPUTERNAME").ok())
+        .unwrap_or_else(|| UNKNOWN_VALUE.to_string())
+}
+
+// Formats the time in the RFC 3339 format in UTC, e.g. "2020-03-01T10:15:30.250Z".
+pub fn format_timestamp(time: SystemTime) -> String {
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = elapsed.as_secs();
+    let (days, seconds_of_day) = ((seconds / 86400) as i64, seconds % 86400);
+
+    // Converts the number of days since the epoch to the civil date.
+    let shifted_days = days + 719468;
+    let era = shifted_days.div_euclid(146097);
+    let day_of_era = shifted_days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::audit::{
+        format_timestamp, get_audit_log_path, read_audit_log, record_invocation, redact_arguments,
+    };
+    use crate::manifest::{Manifest, ManifestEntry};
+    use crate::terraform::CommandContext;
+    use crate::utils::create_test_directory;
+
+    #[test]
+    fn test_record_invocation_uses_entry_of_module() {
+        let directory = create_test_directory("audit");
+        let project_directory = directory.to_string_lossy().into_owned();
+        let config = String::from("dev");
+        let get_entry = |hash: &str| ManifestEntry {
+            template: String::from("main.tpl"),
+            config: config.clone(),
+            hash: hash.to_string(),
+            template_hash: String::new(),
+        };
+        let module = directory.join("main-dev.tf").to_string_lossy().into_owned();
+        let stale_module = directory.join("main.tf").to_string_lossy().into_owned();
+        let mut manifest = Manifest::load(&directory);
+        manifest.insert(&stale_module, get_entry("stale"));
+        manifest.insert(&module, get_entry("actual"));
+        manifest.save().unwrap();
+
+        let context = CommandContext::new(&project_directory, &config, Some(&module)).unwrap();
+        let args = vec![String::from("-input=false")];
+        record_invocation("plan", &args, &context, Some(0), Duration::from_millis(5));
+        let context = CommandContext::new(&project_directory, &config, None).unwrap();
+        record_invocation("output", &args, &context, Some(1), Duration::from_millis(5));
+        let records = read_audit_log(&get_audit_log_path(&project_directory).unwrap()).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].config, "dev");
+        assert_eq!(records[0].command, "plan");
+        assert_eq!(records[0].template_hash, Some(String::from("actual")));
+        assert_eq!(records[1].template_hash, None);
+        assert_eq!(records[1].exit_code, Some(1));
+    }
+
+    #[test]
+    fn test_redact_arguments() {
+        let args: Vec<String> = vec![
+            "-var=db_password=qwerty",
+            "-var",
+            "token=secret",
+            "-backend-config=bucket=states",
+            "-backend-config=backend.hcl",
+            "-var-file=configs/dev/variables.tfvars",
+            "-out=plan.tfplan",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        assert_eq!(
+            redact_arguments(&args),
+            vec![
+                "-var=db_password=********",
+                "-var",
+                "token=********",
+                "-backend-config=bucket=********",
+                "-backend-config=backend.hcl",
+                "-var-file=configs/dev/variables.tfvars",
+                "-out=plan.tfplan",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_583_057_730_250);
+        assert_eq!(format_timestamp(time), "2020-03-01T10:15:30.250Z");
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    }
+}
@@ -1,5 +1,7 @@
 use structopt::StructOpt;
 
+use crate::report::{parse_report_target, ReportTarget};
+
 #[derive(StructOpt, Debug)]
 #[structopt(name = "terraform-sage")]
 pub enum Command {
@@ -88,6 +90,27 @@ pub enum Command {
         )]
         out: Option<String>,
 
+        #[structopt(
+            long = "stacks",
+            conflicts_with = "target",
+            help = "Run the command for the stacks of the project (comma-separated names or `all`)"
+        )]
+        stacks: Option<String>,
+
+        #[structopt(
+            long = "summary-only",
+            help = "Print only the summary of the planned changes without Terraform output"
+        )]
+        summary_only: bool,
+
+        #[structopt(
+            long = "report",
+            number_of_values = 1,
+            parse(try_from_str = parse_report_target),
+            help = "Save the report to the file: `junit:<path>` or `markdown:<path>` (can be repeated)"
+        )]
+        report: Vec<ReportTarget>,
+
         #[structopt(
             long = "--cleanup",
             help = "Delete main.tf module after initialization."
@@ -135,6 +158,25 @@ pub enum Command {
         )]
         out: Option<String>,
 
+        #[structopt(
+            long = "stacks",
+            conflicts_with = "target",
+            help = "Run the command for the stacks of the project (comma-separated names or `all`)"
+        )]
+        stacks: Option<String>,
+
+        #[structopt(
+            long = "policy",
+            help = "Path to the policy file to check the planned changes against"
+        )]
+        policy: Option<String>,
+
+        #[structopt(
+            long = "allow-deletes",
+            help = "Allow deleting resources in the protected configuration without confirmation"
+        )]
+        allow_deletes: bool,
+
         #[structopt(
             long = "--cleanup",
             help = "Delete main.tf module after initialization."
@@ -182,6 +224,12 @@ pub enum Command {
         )]
         out: Option<String>,
 
+        #[structopt(
+            long = "allow-deletes",
+            help = "Allow deleting resources in the protected configuration without confirmation"
+        )]
+        allow_deletes: bool,
+
         #[structopt(
             long = "--cleanup",
             help = "Delete main.tf module after initialization."
@@ -229,6 +277,24 @@ pub enum Command {
         )]
         out: Option<String>,
 
+        #[structopt(
+            short = "f",
+            long = "format",
+            default_value = "table",
+            possible_values = &["table", "json", "env", "dotenv", "tfvars"],
+            help = "Output format of the variables"
+        )]
+        format: String,
+
+        #[structopt(long = "show-sensitive", help = "Show values of the sensitive outputs")]
+        show_sensitive: bool,
+
+        #[structopt(
+            long = "raw",
+            help = "Print only the value of the output with the given name, without quotes"
+        )]
+        raw: Option<String>,
+
         #[structopt(
             long = "--cleanup",
             help = "Delete main.tf module after initialization."
@@ -248,12 +314,15 @@ pub enum Command {
             help = "Path to directory with Terraform files"
         )]
         directory: String,
+
+        #[structopt(long = "tag", help = "Show only configurations with the given tag")]
+        tag: Option<String>,
     },
     #[structopt(name = "generate")]
     /// Generate main.tf from the template file
     Generate {
-        #[structopt(required = true, help = "Configuration name")]
-        config: String,
+        #[structopt(required_unless = "all", help = "Configuration name")]
+        config: Option<String>,
 
         #[structopt(
             short = "d",
@@ -278,5 +347,456 @@ pub enum Command {
             help = "Path to the generated file (*.tf)"
         )]
         out: String,
+
+        #[structopt(
+            long = "stdout",
+            conflicts_with_all = &["diff", "check", "force"],
+            help = "Print the rendered template to stdout instead of writing the file"
+        )]
+        stdout: bool,
+
+        #[structopt(
+            long = "diff",
+            help = "Show a diff between the existing file and the rendered template"
+        )]
+        diff: bool,
+
+        #[structopt(
+            long = "check",
+            help = "Exit with non-zero code if the existing file differs from the rendered template"
+        )]
+        check: bool,
+
+        #[structopt(
+            long = "force",
+            help = "Overwrite the existing file even if it wasn't generated by terraform-sage"
+        )]
+        force: bool,
+
+        #[structopt(
+            long = "all",
+            conflicts_with_all = &["config", "stdout", "diff", "check"],
+            help = "Render all configurations into the directory specified in --out-dir"
+        )]
+        all: bool,
+
+        #[structopt(
+            long = "out-dir",
+            default_value = "build",
+            help = "Path to the directory for the rendered configurations"
+        )]
+        out_dir: String,
+
+        #[structopt(
+            long = "diff-against",
+            requires = "all",
+            help = "Path to the directory with previously rendered configurations to compare with"
+        )]
+        diff_against: Option<String>,
+    },
+    #[structopt(name = "context")]
+    /// Inspect the template context of configurations
+    Context(ContextCommand),
+    #[structopt(name = "vars")]
+    /// Inspect the variable files of configurations
+    Vars(VarsCommand),
+    #[structopt(name = "new")]
+    /// Create a new project with the template and configurations
+    New {
+        #[structopt(required = true, help = "Path to the project directory")]
+        directory: String,
+
+        #[structopt(
+            short = "b",
+            long = "backend",
+            default_value = "local",
+            possible_values = &["local", "s3", "gcs"],
+            help = "Backend storage for Terraform states"
+        )]
+        backend: String,
+    },
+    #[structopt(name = "env")]
+    /// Manage configurations of the project
+    Env(EnvCommand),
+    /// Check the generated Terraform modules of configurations for errors
+    #[structopt(
+        name = "validate",
+        setting = structopt::clap::AppSettings::TrailingVarArg
+    )]
+    Validate {
+        #[structopt(required_unless = "all", help = "Configuration name")]
+        config: Option<String>,
+
+        #[structopt(
+            short = "d",
+            long = "dir",
+            default_value = ".",
+            help = "Path to directory with Terraform files"
+        )]
+        directory: String,
+
+        #[structopt(
+            long = "all",
+            conflicts_with = "config",
+            help = "Check all available configurations"
+        )]
+        all: bool,
+
+        #[structopt(
+            long = "template",
+            default_value = "main.tpl",
+            help = "File name of the used template module (*.tpl)"
+        )]
+        template: String,
+
+        #[structopt(
+            long = "report",
+            number_of_values = 1,
+            parse(try_from_str = parse_report_target),
+            help = "Save the report to the file: `junit:<path>` or `markdown:<path>` (can be repeated)"
+        )]
+        report: Vec<ReportTarget>,
+
+        #[structopt(hidden = true, help = "Extra options for Terraform validate command")]
+        extra: Vec<String>,
+    },
+    /// Detect changes of the infrastructure made outside of Terraform
+    #[structopt(
+        name = "drift",
+        setting = structopt::clap::AppSettings::TrailingVarArg
+    )]
+    Drift {
+        #[structopt(required_unless = "all", help = "Configuration name")]
+        config: Option<String>,
+
+        #[structopt(
+            short = "d",
+            long = "dir",
+            default_value = ".",
+            help = "Path to directory with Terraform files"
+        )]
+        directory: String,
+
+        #[structopt(
+            long = "all",
+            conflicts_with = "config",
+            help = "Check all available configurations"
+        )]
+        all: bool,
+
+        #[structopt(
+            long = "template",
+            default_value = "main.tpl",
+            help = "File name of the used template module (*.tpl)"
+        )]
+        template: String,
+
+        #[structopt(
+            long = "full",
+            help = "Run a regular plan instead of the refresh-only one to report the changes of the configuration too"
+        )]
+        full: bool,
+
+        #[structopt(
+            short = "f",
+            long = "format",
+            default_value = "text",
+            possible_values = &["text", "json", "junit"],
+            help = "Output format of the report"
+        )]
+        format: String,
+
+        #[structopt(
+            long = "report",
+            number_of_values = 1,
+            parse(try_from_str = parse_report_target),
+            help = "Save the report to the file: `junit:<path>` or `markdown:<path>` (can be repeated)"
+        )]
+        report: Vec<ReportTarget>,
+
+        #[structopt(hidden = true, help = "Extra options for Terraform plan command")]
+        extra: Vec<String>,
+    },
+    #[structopt(name = "policy")]
+    /// Check the planned changes against the policy rules
+    Policy(PolicyCommand),
+    #[structopt(name = "lint")]
+    /// Check the template placeholders against the context of each configuration
+    Lint {
+        #[structopt(
+            short = "d",
+            long = "dir",
+            default_value = ".",
+            help = "Path to directory with Terraform files"
+        )]
+        directory: String,
+
+        #[structopt(
+            short = "t",
+            long = "template",
+            default_value = "main.tpl",
+            help = "Path to the template file (*.tpl)"
+        )]
+        template: String,
+
+        #[structopt(
+            long = "report",
+            number_of_values = 1,
+            parse(try_from_str = parse_report_target),
+            help = "Save the report to the file: `junit:<path>` or `markdown:<path>` (can be repeated)"
+        )]
+        report: Vec<ReportTarget>,
+    },
+    #[structopt(name = "history")]
+    /// Show the Terraform commands recorded in the audit log
+    History {
+        #[structopt(help = "Configuration name")]
+        config: Option<String>,
+
+        #[structopt(
+            short = "d",
+            long = "dir",
+            default_value = ".",
+            help = "Path to directory with Terraform files"
+        )]
+        directory: String,
+
+        #[structopt(
+            short = "n",
+            long = "limit",
+            default_value = "20",
+            help = "Number of the latest records to show"
+        )]
+        limit: usize,
+
+        #[structopt(
+            short = "f",
+            long = "format",
+            default_value = "table",
+            possible_values = &["table", "json"],
+            help = "Output format of the records"
+        )]
+        format: String,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+pub enum ContextCommand {
+    #[structopt(name = "show")]
+    /// Print the resolved template context for the configuration
+    Show {
+        #[structopt(required = true, help = "Configuration name")]
+        config: String,
+
+        #[structopt(
+            short = "d",
+            long = "dir",
+            default_value = ".",
+            help = "Path to directory with Terraform files"
+        )]
+        directory: String,
+
+        #[structopt(
+            short = "f",
+            long = "format",
+            default_value = "toml",
+            possible_values = &["toml", "json"],
+            help = "Output format of the context"
+        )]
+        format: String,
+
+        #[structopt(
+            long = "source",
+            help = "Annotate each key with the file and section it came from"
+        )]
+        source: bool,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+pub enum VarsCommand {
+    #[structopt(name = "check")]
+    /// Check the variable files against the declared variables
+    Check {
+        #[structopt(required_unless = "all", help = "Configuration name")]
+        config: Option<String>,
+
+        #[structopt(
+            short = "d",
+            long = "dir",
+            default_value = ".",
+            help = "Path to directory with Terraform files"
+        )]
+        directory: String,
+
+        #[structopt(
+            short = "t",
+            long = "template",
+            default_value = "main.tpl",
+            help = "Path to the template file (*.tpl)"
+        )]
+        template: String,
+
+        #[structopt(
+            long = "all",
+            conflicts_with = "config",
+            help = "Check all available configurations"
+        )]
+        all: bool,
+    },
+    #[structopt(name = "diff")]
+    /// Compare the variables and the template context of two configurations
+    Diff {
+        #[structopt(required = true, help = "Name of the first configuration")]
+        left: String,
+
+        #[structopt(required = true, help = "Name of the second configuration")]
+        right: String,
+
+        #[structopt(
+            short = "d",
+            long = "dir",
+            default_value = ".",
+            help = "Path to directory with Terraform files"
+        )]
+        directory: String,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+pub enum PolicyCommand {
+    /// Plan the changes for the configuration and check them against the policy rules
+    #[structopt(
+        name = "check",
+        setting = structopt::clap::AppSettings::TrailingVarArg
+    )]
+    Check {
+        #[structopt(required = true, help = "Configuration name")]
+        config: String,
+
+        #[structopt(
+            short = "d",
+            long = "dir",
+            default_value = ".",
+            help = "Path to directory with Terraform files"
+        )]
+        directory: String,
+
+        #[structopt(
+            short = "t",
+            long = "target",
+            help = "Path to the main Terraform module (*.tf)"
+        )]
+        target: Option<String>,
+
+        #[structopt(
+            long = "template",
+            default_value = "main.tpl",
+            help = "File name of the used template module (*.tpl)"
+        )]
+        template: String,
+
+        #[structopt(
+            short = "o",
+            long = "out",
+            help = "File name of the generated Terraform module (*.tf)"
+        )]
+        out: Option<String>,
+
+        #[structopt(
+            long = "policy",
+            help = "Path to the policy file to check the planned changes against"
+        )]
+        policy: Option<String>,
+
+        #[structopt(
+            long = "--cleanup",
+            help = "Delete main.tf module after initialization."
+        )]
+        cleanup: bool,
+
+        #[structopt(hidden = true, help = "Extra options for Terraform plan command")]
+        extra: Vec<String>,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+pub enum EnvCommand {
+    #[structopt(name = "add")]
+    /// Create a new configuration
+    Add {
+        #[structopt(required = true, help = "Configuration name")]
+        name: String,
+
+        #[structopt(
+            short = "d",
+            long = "dir",
+            default_value = ".",
+            help = "Path to directory with Terraform files"
+        )]
+        directory: String,
+
+        #[structopt(
+            long = "from",
+            help = "Configuration to copy the variable files and the context from"
+        )]
+        from: Option<String>,
+    },
+    #[structopt(name = "rename")]
+    /// Rename the configuration
+    Rename {
+        #[structopt(required = true, help = "Configuration name")]
+        name: String,
+
+        #[structopt(required = true, help = "New configuration name")]
+        new_name: String,
+
+        #[structopt(
+            short = "d",
+            long = "dir",
+            default_value = ".",
+            help = "Path to directory with Terraform files"
+        )]
+        directory: String,
+
+        #[structopt(
+            long = "template",
+            default_value = "main.tpl",
+            help = "File name of the used template module (*.tpl)"
+        )]
+        template: String,
+
+        #[structopt(
+            long = "force",
+            help = "Rename the configuration even if its Terraform backend changes"
+        )]
+        force: bool,
+    },
+    #[structopt(name = "remove")]
+    /// Remove the configuration with its context and Terraform data
+    Remove {
+        #[structopt(required = true, help = "Configuration name")]
+        name: String,
+
+        #[structopt(
+            short = "d",
+            long = "dir",
+            default_value = ".",
+            help = "Path to directory with Terraform files"
+        )]
+        directory: String,
+
+        #[structopt(
+            long = "template",
+            default_value = "main.tpl",
+            help = "File name of the used template module (*.tpl)"
+        )]
+        template: String,
+
+        #[structopt(
+            long = "force",
+            help = "Remove the configuration even if its Terraform state is not empty"
+        )]
+        force: bool,
     },
 }
@@ -1,12 +1,61 @@
-use std::path::Path;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
 
-use crate::cli::Command;
+use quick_error::ResultExt;
+use serde_json::Value as JsonValue;
+
+use crate::audit::{get_audit_log_path, read_audit_log};
+use crate::cli::{Command, ContextCommand, EnvCommand, PolicyCommand, VarsCommand};
+use crate::drift::{
+    format_drift_json, get_drift_report_cases, DriftResult, DriftStatus, DRIFT_REPORT_NAME,
+};
 use crate::error::SageError;
-use crate::template::{generate_file_name, generate_from_template};
-use crate::terminal::{print_command_done, print_error, print_info, print_warning};
-use crate::terraform::TerraformClient;
-use crate::utils::{get_configs, is_correct_config};
+use crate::git::{check_git_rules, GitRuleViolation};
+use crate::hooks::{
+    run_hooks, HookContext, AFTER_HOOK_PREFIX, BEFORE_HOOK_PREFIX, ON_FAILURE_HOOK,
+};
+use crate::lint::{lint_project, LINT_REPORT_NAME};
+use crate::manifest::{Manifest, MANIFEST_FILE_NAME, SAGE_DIRECTORY_NAME};
+use crate::metadata::{get_config_metadata, ConfigMetadata};
+use crate::outputs::{
+    forget_cached_outputs, format_output_value, format_outputs, format_raw_output, get_outputs,
+    resolve_stack_outputs, save_cached_outputs,
+};
+use crate::plan::{
+    format_plan_markdown, get_destructive_changes, get_plan_changes, get_plan_drift,
+    get_plan_summary, get_prevented_changes, ResourceChange, PLAN_REPORT_NAME,
+};
+use crate::policy::{Policy, POLICY_FILE_NAME};
+use crate::project::ProjectConfig;
+use crate::report::{format_junit, write_reports, Report, ReportCase, ReportOutcome, ReportTarget};
+use crate::scaffold::{add_environment, create_project, remove_environment, rename_environment};
+use crate::template::{
+    diff_rendered_file, format_context, generate_file_name, generate_from_template,
+    generate_tracked_file, get_outdated_reason, get_template_context, render, render_from_template,
+    warn_missing_context_section, DEFAULTS_SECTION_NAME,
+};
+use crate::terminal::{
+    ask_confirmation, print_command_done, print_destructive_changes, print_error, print_info,
+    print_messages_to_stderr, print_plan_summary, print_policy_violations, print_table,
+    print_warning,
+};
+use crate::terraform::{
+    CommandContext, TerraformClient, AUTO_APPROVE_ARG, DESTROY_ARG, DETAILED_EXITCODE_ARG,
+    LOCK_FILE_NAME, NO_INPUT_ARG, NO_LOCK_ARG, PLAN_OUT_ARG, REFRESH_ONLY_ARG,
+    REFRESH_ONLY_VERSION, VALIDATE_REPORT_NAME,
+};
+use crate::utils::{
+    get_configs, get_data_directory, get_different_files, get_leaf_directories, get_plan_file,
+    is_correct_config, write_file_atomically, CONFIG_NAME_SEPARATOR,
+};
+use crate::vars::{
+    check_variables, compare_values, get_auto_variable_files, get_configuration_values,
+    get_variable_assignments, get_variable_definitions, ValueDifference,
+};
 
+#[derive(Default)]
 pub struct SageClient {
     terraform: TerraformClient,
 }
@@ -19,8 +68,16 @@ impl SageClient {
         }
     }
 
-    // An entry point for Terraform-Sage client.
-    pub fn run(&self, command: &Command) {
+    // An entry point for Terraform-Sage client. Returns the exit code
+    // for the process.
+    pub fn run(&self, command: &Command) -> i32 {
+        if prints_data(command) {
+            print_messages_to_stderr();
+        }
+        get_named_configs(command)
+            .into_iter()
+            .for_each(|(config, directory)| warn_missing_context_section(directory, config));
+
         let result = match command {
             Command::Init {
                 config,
@@ -30,58 +87,252 @@ impl SageClient {
                 out,
                 cleanup,
                 extra,
-            } => self.init_terraform(config, directory, target, template, out, *cleanup, extra),
+            } => self.run_with_hooks("init", config, directory, None, || {
+                self.init_terraform(config, directory, target, template, out, *cleanup, extra)
+            }),
             Command::Plan {
                 config,
                 directory,
                 target,
                 template,
                 out,
+                stacks: None,
+                summary_only,
+                report,
                 cleanup,
                 extra,
-            } => self.generate_plan_execution(config, directory, target, template, out, *cleanup, extra),
+            } => self.run_with_hooks("plan", config, directory, None, || {
+                self.generate_plan_execution(
+                    config,
+                    directory,
+                    target,
+                    template,
+                    out,
+                    *summary_only,
+                    report,
+                    *cleanup,
+                    extra,
+                )
+            }),
             Command::Apply {
                 config,
                 directory,
                 target,
                 template,
                 out,
+                stacks: None,
+                policy,
+                allow_deletes,
+                cleanup,
+                extra,
+            } => self
+                .check_git_rules("apply", config, directory)
+                .and_then(|_| {
+                    self.run_with_hooks("apply", config, directory, None, || {
+                        self.apply_changes(
+                            config,
+                            directory,
+                            target,
+                            template,
+                            out,
+                            policy,
+                            *allow_deletes,
+                            *cleanup,
+                            extra,
+                        )
+                    })
+                }),
+            Command::Plan {
+                config,
+                directory,
+                template,
+                out,
+                stacks: Some(stacks),
+                cleanup,
+                extra,
+                ..
+            }
+            | Command::Apply {
+                config,
+                directory,
+                template,
+                out,
+                stacks: Some(stacks),
                 cleanup,
                 extra,
-            } => self.apply_changes(config, directory, target, template, out, *cleanup, extra),
+                ..
+            } => self.run_stacks(
+                command, config, directory, stacks, template, out, *cleanup, extra,
+            ),
             Command::Destroy {
                 config,
                 directory,
                 target,
                 template,
                 out,
+                allow_deletes,
                 cleanup,
                 extra,
-            } => self.destroy_infrastructure(config, directory, target, template, out, *cleanup, extra),
+            } => self
+                .check_git_rules("destroy", config, directory)
+                .and_then(|_| {
+                    self.run_with_hooks("destroy", config, directory, None, || {
+                        self.destroy_infrastructure(
+                            config,
+                            directory,
+                            target,
+                            template,
+                            out,
+                            *allow_deletes,
+                            *cleanup,
+                            extra,
+                        )
+                    })
+                }),
             Command::Output {
                 config,
                 directory,
                 target,
                 template,
                 out,
+                format,
+                show_sensitive,
+                raw,
                 cleanup,
                 extra,
-            } => self.output_variables(config, directory, target, template, out, *cleanup, extra),
-            Command::List { directory } => self.show_configurations(directory),
+            } => self.output_variables(
+                config,
+                directory,
+                target,
+                template,
+                out,
+                format,
+                *show_sensitive,
+                raw,
+                *cleanup,
+                extra,
+            ),
+            Command::List { directory, tag } => self.show_configurations(directory, tag),
             Command::Generate {
                 directory,
                 config,
                 template,
                 out,
-            } => match self.generate_main_tf(directory, config, template, out) {
-                Ok(_out_filepath) => Ok(()),
-                Err(err) => Err(err),
+                stdout,
+                diff,
+                check,
+                force,
+                all,
+                out_dir,
+                diff_against,
+            } => match (all, config) {
+                (true, _) => self.generate_all_main_tf(
+                    directory,
+                    template,
+                    out,
+                    out_dir,
+                    diff_against,
+                    *force,
+                ),
+                (false, Some(config)) if *stdout => self.print_main_tf(directory, config, template),
+                (false, Some(config)) => {
+                    self.update_main_tf(directory, config, template, out, *diff, *check, *force)
+                }
+                (false, None) => {
+                    let message = String::from("Configuration name was not specified.");
+                    Err(SageError::InvalidConfig(message))
+                }
             },
+            Command::Context(ContextCommand::Show {
+                config,
+                directory,
+                format,
+                source,
+            }) => self.show_context(config, directory, format, *source),
+            Command::Vars(VarsCommand::Check {
+                config,
+                directory,
+                template,
+                all,
+            }) => self.check_variable_files(config, directory, template, *all),
+            Command::Vars(VarsCommand::Diff {
+                left,
+                right,
+                directory,
+            }) => self.compare_configurations(left, right, directory),
+            Command::New { directory, backend } => self.create_project(directory, backend),
+            Command::Env(EnvCommand::Add {
+                name,
+                directory,
+                from,
+            }) => self.add_configuration(name, directory, from),
+            Command::Env(EnvCommand::Rename {
+                name,
+                new_name,
+                directory,
+                template,
+                force,
+            }) => self.rename_configuration(name, new_name, directory, template, *force),
+            Command::Env(EnvCommand::Remove {
+                name,
+                directory,
+                template,
+                force,
+            }) => self.remove_configuration(name, directory, template, *force),
+            Command::Lint {
+                directory,
+                template,
+                report,
+            } => self.lint_template(directory, template, report),
+            Command::Validate {
+                config,
+                directory,
+                all,
+                template,
+                report,
+                extra,
+            } => self.validate_configurations(config, directory, *all, template, report, extra),
+            Command::Drift {
+                config,
+                directory,
+                all,
+                template,
+                full,
+                format,
+                report,
+                extra,
+            } => self.detect_drift(
+                config, directory, *all, template, *full, format, report, extra,
+            ),
+            Command::Policy(PolicyCommand::Check {
+                config,
+                directory,
+                target,
+                template,
+                out,
+                policy,
+                cleanup,
+                extra,
+            }) => self.check_plan_policy(
+                config, directory, target, template, out, policy, *cleanup, extra,
+            ),
+            Command::History {
+                config,
+                directory,
+                limit,
+                format,
+            } => self.show_history(config, directory, *limit, format),
         };
 
         match result {
-            Ok(_) => print_command_done(),
-            Err(e) => print_error(e),
+            Ok(_) => {
+                print_command_done();
+                0
+            }
+            Err(e) => {
+                print_error(e);
+                1
+            }
         }
     }
 
@@ -111,8 +362,10 @@ impl SageClient {
         is_correct_config(config, configs)?;
         let out_filename = Some(out.clone().unwrap_or(String::from("main.tf")));
         let main_filepath = self.get_main_tf(directory, config, target, template, &out_filename)?;
-        let terraform_args = self.terraform.get_init_args(directory, extra);
-        self.terraform.call_without_input("init", &terraform_args)?;
+        let terraform_args = self.terraform.get_init_args(extra);
+        let context = CommandContext::new(directory, config, Some(&main_filepath))?;
+        self.terraform
+            .call_without_input("init", &terraform_args, &context)?;
 
         if cleanup {
             self.terraform.delete_main_tf(&main_filepath)?;
@@ -132,6 +385,9 @@ impl SageClient {
     // For deleting the template-based files with the name specified in `out`
     // option and saved by `directory` path, just specify the --cleanup option
     // before executing init command.
+    //
+    // With the `report` targets the result of the plan, the results of the
+    // policy rules and the summary of the planned changes are saved as reports.
     fn generate_plan_execution(
         &self,
         config: &String,
@@ -139,22 +395,259 @@ impl SageClient {
         target: &Option<String>,
         template: &String,
         out: &Option<String>,
+        summary_only: bool,
+        report: &Vec<ReportTarget>,
+        cleanup: bool,
+        extra: &Vec<String>,
+    ) -> Result<(), SageError> {
+        let mut plan_report = Report::default();
+        let result = self.plan_changes(
+            config,
+            directory,
+            target,
+            template,
+            out,
+            summary_only,
+            None,
+            &mut plan_report,
+            cleanup,
+            extra,
+        );
+        if !report.is_empty() {
+            write_reports(
+                report,
+                PLAN_REPORT_NAME,
+                &plan_report.cases,
+                &plan_report.sections,
+            )?;
+        }
+        result
+    }
+
+    // Plans the changes of the configuration with `config` name, prints their
+    // summary and adds the result of the plan to the `plan_report`. When the
+    // `stack` is set, then the report cases are prefixed with its name.
+    fn plan_changes(
+        &self,
+        config: &String,
+        directory: &String,
+        target: &Option<String>,
+        template: &String,
+        out: &Option<String>,
+        summary_only: bool,
+        stack: Option<&String>,
+        plan_report: &mut Report,
         cleanup: bool,
         extra: &Vec<String>,
     ) -> Result<(), SageError> {
+        let result = self.create_plan(
+            config,
+            directory,
+            target,
+            template,
+            out,
+            summary_only,
+            extra,
+        );
+        self.delete_saved_plan(directory, config);
+        self.add_plan_report(config, directory, stack, &result, plan_report)?;
+        let (main_filepath, _, changes) = result?;
+        print_plan_summary(&get_plan_summary(&changes));
+        print_destructive_changes(&get_destructive_changes(&changes));
+
+        if cleanup {
+            self.terraform.delete_main_tf(&main_filepath)?;
+        };
+        Ok(())
+    }
+
+    // Adds the result of the plan of the configuration with `config` name to
+    // the report. Besides the result of the plan, the report contains the
+    // results of the rules from the policy file of the project, if it exists.
+    fn add_plan_report(
+        &self,
+        config: &String,
+        directory: &String,
+        stack: Option<&String>,
+        result: &Result<(String, String, Vec<ResourceChange>), SageError>,
+        plan_report: &mut Report,
+    ) -> Result<(), SageError> {
+        let get_name = |name: &String| match stack {
+            Some(stack) => format!("{}{}{}", stack, CONFIG_NAME_SEPARATOR, name),
+            None => name.clone(),
+        };
+        let name = get_name(config);
+        let (outcome, policy_cases) = match result {
+            Ok((_, _, changes)) => {
+                let policy_cases = match Policy::load(directory, &None)? {
+                    Some(policy) => policy.get_report_cases(config, changes),
+                    None => vec![],
+                };
+                plan_report
+                    .sections
+                    .push(format_plan_markdown(&name, changes));
+                (ReportOutcome::Passed, policy_cases)
+            }
+            Err(err) => (ReportOutcome::Error(err.to_string()), vec![]),
+        };
+
+        plan_report.cases.push(ReportCase {
+            name: name.clone(),
+            group: PLAN_REPORT_NAME.to_string(),
+            outcome,
+            details: vec![],
+        });
+        plan_report
+            .cases
+            .extend(policy_cases.into_iter().map(|case| ReportCase {
+                name: get_name(&case.name),
+                ..case
+            }));
+        Ok(())
+    }
+
+    // Generates the main module and saves the execution plan of the configuration
+    // with `config` name. Returns the path to the main module, the path to the
+    // plan file and the planned changes. Terraform output is hidden when the
+    // `quiet` flag is set.
+    fn create_plan(
+        &self,
+        config: &String,
+        directory: &String,
+        target: &Option<String>,
+        template: &String,
+        out: &Option<String>,
+        quiet: bool,
+        extra: &Vec<String>,
+    ) -> Result<(String, String, Vec<ResourceChange>), SageError> {
         let configs = get_configs(directory)?;
         is_correct_config(config, configs.clone())?;
         let configs_copy = configs.clone();
         let configs_path = configs_copy.get(config).unwrap();
         let out_filename = Some(out.clone().unwrap_or(String::from("main.tf")));
         let main_filepath = self.get_main_tf(directory, config, target, template, &out_filename)?;
-        let terraform_args = self.terraform.get_command_args(configs_path, directory, extra);
-        self.terraform.call_without_input("plan", &terraform_args)?;
+        let mut terraform_args = self
+            .terraform
+            .get_command_args(configs_path, directory, extra);
+        let plan_file = self.prepare_plan_file(directory, config, &mut terraform_args)?;
+        let context = CommandContext::new(directory, config, Some(&main_filepath))?;
+        match quiet {
+            true => {
+                print_info("Planning changes...");
+                self.terraform
+                    .call_with_output("plan", &terraform_args, &context)?;
+            }
+            false => self
+                .terraform
+                .call_without_input("plan", &terraform_args, &context)?,
+        };
+
+        let changes = get_plan_changes(directory, config, &plan_file)?;
+        Ok((main_filepath, plan_file, changes))
+    }
+
+    // Checks the planned changes of the configuration with `config` name against
+    // the policy rules. The rules are read from the file by path in `policy`
+    // parameter or from the policy file of the project, if it exists.
+    fn check_policy(
+        &self,
+        config: &String,
+        directory: &String,
+        policy: &Option<String>,
+        changes: &Vec<ResourceChange>,
+    ) -> Result<(), SageError> {
+        let policy = match Policy::load(directory, policy)? {
+            Some(policy) => policy,
+            None => return Ok(()),
+        };
+
+        let violations = policy.check(config, changes);
+        if violations.is_empty() {
+            print_info("The planned changes comply with the policy.");
+            return Ok(());
+        }
+        print_policy_violations(&violations);
+        Err(SageError::PolicyViolations(violations.len()))
+    }
+
+    // Plans the changes for the configuration with `config` name and checks
+    // them against the policy rules, without applying. Intended for CI, so
+    // that the Terraform output is hidden and only the summary and the
+    // violations are printed.
+    fn check_plan_policy(
+        &self,
+        config: &String,
+        directory: &String,
+        target: &Option<String>,
+        template: &String,
+        out: &Option<String>,
+        policy: &Option<String>,
+        cleanup: bool,
+        extra: &Vec<String>,
+    ) -> Result<(), SageError> {
+        if Policy::load(directory, policy)?.is_none() {
+            let message = format!(
+                "The {} file was not found, use the --policy option to specify the policy file.",
+                POLICY_FILE_NAME
+            );
+            return Err(SageError::InvalidConfig(message));
+        }
+
+        let result = self.create_plan(config, directory, target, template, out, true, extra);
+        self.delete_saved_plan(directory, config);
+        let (main_filepath, _, changes) = result?;
+        print_plan_summary(&get_plan_summary(&changes));
+        let result = self.check_policy(config, directory, policy, &changes);
 
         if cleanup {
             self.terraform.delete_main_tf(&main_filepath)?;
         };
-        Ok(())
+        result
+    }
+
+    // Returns the path to the file where Terraform saves the plan. If the path
+    // wasn't passed in the `-out` argument, then the plan is saved in the
+    // `.sage/plans` directory of the project. The path in the `-out` argument is
+    // relative to the current directory, so it is replaced by the absolute path,
+    // because Terraform is executed in the project directory.
+    fn prepare_plan_file(
+        &self,
+        directory: &String,
+        config: &String,
+        terraform_args: &mut Vec<String>,
+    ) -> Result<String, SageError> {
+        if let Some(arg) = terraform_args
+            .iter_mut()
+            .find(|arg| arg.starts_with(PLAN_OUT_ARG))
+        {
+            let path = PathBuf::from(&arg[PLAN_OUT_ARG.len()..]);
+            let plan_file = env::current_dir()
+                .map(|dir| dir.join(&path))
+                .unwrap_or(path)
+                .to_string_lossy()
+                .into_owned();
+            *arg = format!("{}{}", PLAN_OUT_ARG, plan_file);
+            return Ok(plan_file);
+        }
+
+        let plan_file = get_plan_file(directory, config);
+        if let Some(plans_directory) = Path::new(&plan_file).parent() {
+            fs::create_dir_all(plans_directory).context(&plan_file)?;
+        }
+        terraform_args.insert(0, format!("{}{}", PLAN_OUT_ARG, plan_file));
+        Ok(plan_file)
+    }
+
+    // Deletes the plan saved in the `.sage/plans` directory for the configuration
+    // with `config` name, because the plan contains the values of the sensitive
+    // variables in plain text. Plans saved by path in the `-out` argument are kept.
+    fn delete_saved_plan(&self, directory: &String, config: &String) {
+        let plan_file = get_plan_file(directory, config);
+        if Path::new(&plan_file).exists() {
+            if let Err(err) = fs::remove_file(&plan_file) {
+                print_warning(&format!("Unable to delete the {} plan: {}", plan_file, err));
+            }
+        }
     }
 
     // Creates or updates infrastructure in according to Terraform configuration.
@@ -170,23 +663,40 @@ impl SageClient {
     // option and saved by `directory` path, just specify the --cleanup option
     // before executing init command.
     fn apply_changes(
-       &self,
+        &self,
         config: &String,
         directory: &String,
         target: &Option<String>,
         template: &String,
         out: &Option<String>,
+        policy: &Option<String>,
+        allow_deletes: bool,
         cleanup: bool,
         extra: &Vec<String>,
     ) -> Result<(), SageError> {
-        let configs = get_configs(directory)?;
-        is_correct_config(config, configs.clone())?;
-        let configs_copy = configs.clone();
-        let configs_path = configs_copy.get(config).unwrap();
-        let out_filename = Some(out.clone().unwrap_or(String::from("main.tf")));
-        let main_filepath = self.get_main_tf(directory, config, target, template, &out_filename)?;
-        let terraform_args = self.terraform.get_command_args(configs_path, directory, extra);
-        self.terraform.call_with_input("apply", &terraform_args)?;
+        let (auto_approve, plan_extra) = self.get_plan_extra(extra, &[]);
+        let result = self
+            .create_plan(config, directory, target, template, out, false, &plan_extra)
+            .and_then(|(main_filepath, plan_file, changes)| {
+                print_plan_summary(&get_plan_summary(&changes));
+                self.check_policy(config, directory, policy, &changes)?;
+                self.approve_changes(
+                    "apply",
+                    config,
+                    directory,
+                    &changes,
+                    auto_approve,
+                    allow_deletes,
+                    "Do you want to apply these changes? Only 'yes' will be accepted:",
+                )?;
+                let context = CommandContext::new(directory, config, Some(&main_filepath))?;
+                self.terraform
+                    .call_with_input("apply", &vec![plan_file], &context)?;
+                forget_cached_outputs(directory, config)?;
+                Ok(main_filepath)
+            });
+        self.delete_saved_plan(directory, config);
+        let main_filepath = result?;
 
         if cleanup {
             self.terraform.delete_main_tf(&main_filepath)?;
@@ -213,17 +723,33 @@ impl SageClient {
         target: &Option<String>,
         template: &String,
         out: &Option<String>,
+        allow_deletes: bool,
         cleanup: bool,
         extra: &Vec<String>,
     ) -> Result<(), SageError> {
-        let configs = get_configs(directory)?;
-        is_correct_config(config, configs.clone())?;
-        let configs_copy = configs.clone();
-        let configs_path = configs_copy.get(config).unwrap();
-        let out_filename = Some(out.clone().unwrap_or(String::from("main.tf")));
-        let main_filepath = self.get_main_tf(directory, config, target, template, &out_filename)?;
-        let terraform_args = self.terraform.get_command_args(configs_path, directory, extra);
-        self.terraform.call_with_input("destroy", &terraform_args)?;
+        let (auto_approve, plan_extra) = self.get_plan_extra(extra, &[DESTROY_ARG]);
+        let result = self
+            .create_plan(config, directory, target, template, out, false, &plan_extra)
+            .and_then(|(main_filepath, plan_file, changes)| {
+                print_plan_summary(&get_plan_summary(&changes));
+                self.check_policy(config, directory, &None, &changes)?;
+                self.approve_changes(
+                    "destroy",
+                    config,
+                    directory,
+                    &changes,
+                    auto_approve,
+                    allow_deletes,
+                    "Do you really want to destroy all resources? Only 'yes' will be accepted:",
+                )?;
+                let context = CommandContext::new(directory, config, Some(&main_filepath))?;
+                self.terraform
+                    .call_with_input("apply", &vec![plan_file], &context)?;
+                forget_cached_outputs(directory, config)?;
+                Ok(main_filepath)
+            });
+        self.delete_saved_plan(directory, config);
+        let main_filepath = result?;
 
         if cleanup {
             self.terraform.delete_main_tf(&main_filepath)?;
@@ -231,7 +757,81 @@ impl SageClient {
         Ok(())
     }
 
-    // Reads output variables from a Terraform state file and prints them.
+    // Returns the extra arguments for the plan command, created before applying
+    // the changes, with the `plan_args` added. The changes are applied from the
+    // saved plan, so that Terraform's -auto-approve argument is removed, and
+    // the returned flag tells whether it was passed.
+    fn get_plan_extra(&self, extra: &Vec<String>, plan_args: &[&str]) -> (bool, Vec<String>) {
+        let auto_approve = extra.iter().skip(1).any(|arg| arg == AUTO_APPROVE_ARG);
+        let mut plan_extra = vec![extra.first().cloned().unwrap_or_default()];
+        plan_extra.extend(plan_args.iter().map(|arg| arg.to_string()));
+        plan_extra.extend(
+            extra
+                .iter()
+                .skip(1)
+                .filter(|arg| *arg != AUTO_APPROVE_ARG)
+                .cloned(),
+        );
+        (auto_approve, plan_extra)
+    }
+
+    // Asks the user to approve the planned changes of the configuration with
+    // `config` name. The deleted and replaced resources are always listed and
+    // the plan is refused when it deletes the resources of the types listed in
+    // `prevent_destroy_types` of the configuration metadata. For protected
+    // configurations the number of deleted resources must be typed, unless the
+    // `allow_deletes` flag is set.
+    fn approve_changes(
+        &self,
+        command: &str,
+        config: &String,
+        directory: &String,
+        changes: &Vec<ResourceChange>,
+        auto_approve: bool,
+        allow_deletes: bool,
+        question: &str,
+    ) -> Result<(), SageError> {
+        let configs = get_configs(directory)?;
+        let metadata = match configs.get(config) {
+            Some(config_directory) => get_config_metadata(directory, config, config_directory)?,
+            None => ConfigMetadata::default(),
+        };
+
+        let destructive_changes = get_destructive_changes(changes);
+        print_destructive_changes(&destructive_changes);
+        let prevented_changes = get_prevented_changes(changes, &metadata.prevent_destroy_types);
+        if !prevented_changes.is_empty() {
+            let addresses: Vec<String> = prevented_changes
+                .iter()
+                .map(|change| change.address.clone())
+                .collect();
+            return Err(SageError::DestroyPrevented(
+                config.clone(),
+                addresses.join(", "),
+            ));
+        }
+
+        let (answer, expected) = match (metadata.protected, destructive_changes.len()) {
+            (true, count) if count > 0 && !allow_deletes => {
+                let question = format!(
+                    "The {} configuration is protected. Type the number of deleted or replaced resources ({}) to confirm:",
+                    config, count
+                );
+                (ask_confirmation(&question), count.to_string())
+            }
+            _ if auto_approve || changes.is_empty() => return Ok(()),
+            _ => (ask_confirmation(question), String::from("yes")),
+        };
+        match answer == expected {
+            true => Ok(()),
+            false => Err(SageError::Cancelled(command.to_string())),
+        }
+    }
+
+    // Reads output variables from a Terraform state file and prints them in
+    // the requested `format`. Values of the sensitive outputs are masked,
+    // unless the `show_sensitive` flag is set. With the `raw` option prints
+    // only the value of the output with the given name.
     //
     // If `target` option contains path to *.tf module, then it will be
     // returned to the caller.
@@ -247,6 +847,9 @@ impl SageClient {
         target: &Option<String>,
         template: &String,
         out: &Option<String>,
+        format: &String,
+        show_sensitive: bool,
+        raw: &Option<String>,
         cleanup: bool,
         extra: &Vec<String>,
     ) -> Result<(), SageError> {
@@ -254,16 +857,261 @@ impl SageClient {
         is_correct_config(config, configs.clone())?;
         let out_filename = Some(out.clone().unwrap_or(String::from("main.tf")));
         let main_filepath = self.get_main_tf(directory, config, target, template, &out_filename)?;
-        let mut terraform_args = Vec::new();
-        terraform_args.extend(extra.iter().cloned());
-        self.terraform.call_without_input("output", &terraform_args)?;
+        let outputs = get_outputs(directory, config, extra);
 
         if cleanup {
             self.terraform.delete_main_tf(&main_filepath)?;
         };
+
+        let outputs = outputs?;
+        if extra.len() <= 1 {
+            save_cached_outputs(directory, config, &outputs)?;
+        }
+        match (raw, format.as_str()) {
+            (Some(name), _) => println!("{}", format_raw_output(&outputs, name, show_sensitive)?),
+            (None, "table") => {
+                let rows: Vec<Vec<String>> = outputs
+                    .iter()
+                    .map(|(name, output)| {
+                        let value = format_output_value(output, show_sensitive);
+                        let sensitive = String::from(if output.sensitive { "yes" } else { "no" });
+                        vec![name.clone(), value, sensitive]
+                    })
+                    .collect();
+                print_table(&["NAME", "VALUE", "SENSITIVE"], &rows);
+            }
+            (None, format) => println!("{}", format_outputs(&outputs, format, show_sensitive)),
+        };
         Ok(())
     }
 
+    // Checks the generated main module of the configuration with `config` name,
+    // or of all configurations with the `all` flag, via Terraform's validate
+    // command. The configurations must be initialized. With the `report`
+    // targets the results are saved as reports, one case per configuration.
+    fn validate_configurations(
+        &self,
+        config: &Option<String>,
+        directory: &String,
+        all: bool,
+        template: &String,
+        report: &Vec<ReportTarget>,
+        extra: &Vec<String>,
+    ) -> Result<(), SageError> {
+        let configs = get_configs(directory)?;
+        let config_names: Vec<String> = match (all, config) {
+            (false, Some(config)) => {
+                is_correct_config(config, configs.clone())?;
+                vec![config.clone()]
+            }
+            _ => {
+                let mut config_names: Vec<String> = configs.keys().cloned().collect();
+                config_names.sort();
+                config_names
+            }
+        };
+
+        let terraform_args = self.terraform.get_validate_args(extra);
+        let out_filename = Some(String::from("main.tf"));
+        let mut rows = Vec::new();
+        let mut cases = Vec::new();
+        for config in config_names.iter() {
+            print_info(&format!("Validating {} configuration...", config));
+            let result = self
+                .get_main_tf(directory, config, &None, template, &out_filename)
+                .and_then(|main_filepath| {
+                    let context = CommandContext::new(directory, config, Some(&main_filepath))?;
+                    self.terraform
+                        .call_with_output("validate", &terraform_args, &context)
+                });
+
+            let (status, outcome, details) = match result {
+                Ok(_) => (String::from("valid"), ReportOutcome::Passed, vec![]),
+                Err(err) => {
+                    print_warning(&format!("[{}] {}", config, err));
+                    let details = err.to_string().lines().map(String::from).collect();
+                    let outcome = ReportOutcome::Failed(String::from("validation failed"));
+                    (String::from("invalid"), outcome, details)
+                }
+            };
+            rows.push(vec![config.clone(), status]);
+            cases.push(ReportCase {
+                name: config.clone(),
+                group: VALIDATE_REPORT_NAME.to_string(),
+                outcome,
+                details,
+            });
+        }
+
+        print_table(&["CONFIG", "RESULT"], &rows);
+        write_reports(report, VALIDATE_REPORT_NAME, &cases, &vec![])?;
+        let failed_count = cases
+            .iter()
+            .filter(|case| case.outcome != ReportOutcome::Passed)
+            .count();
+        match failed_count {
+            0 => Ok(()),
+            count => Err(SageError::ValidationFailed(count)),
+        }
+    }
+
+    // Checks the configuration with `config` name, or all configurations with
+    // the `all` flag, for the changes of the infrastructure made outside of
+    // Terraform and prints the report in the given `format`. By default runs
+    // a refresh-only plan, the `full` flag switches to a regular plan, so that
+    // the changes of the configuration are reported too. Returns an error when
+    // any configuration has drifted or couldn't be checked.
+    fn detect_drift(
+        &self,
+        config: &Option<String>,
+        directory: &String,
+        all: bool,
+        template: &String,
+        full: bool,
+        format: &String,
+        report: &Vec<ReportTarget>,
+        extra: &Vec<String>,
+    ) -> Result<(), SageError> {
+        let configs = get_configs(directory)?;
+        let config_names: Vec<String> = match (all, config) {
+            (false, Some(config)) => {
+                is_correct_config(config, configs.clone())?;
+                vec![config.clone()]
+            }
+            _ => {
+                let mut config_names: Vec<String> = configs.keys().cloned().collect();
+                config_names.sort();
+                config_names
+            }
+        };
+
+        let version = self.terraform.get_version();
+        let full = match (full, version) {
+            (false, Some(version)) if version < REFRESH_ONLY_VERSION => {
+                print_warning(&format!(
+                    "Refresh-only plans require Terraform {}.{}.{} or newer, a regular plan is used instead.",
+                    REFRESH_ONLY_VERSION.0, REFRESH_ONLY_VERSION.1, REFRESH_ONLY_VERSION.2
+                ));
+                true
+            }
+            _ => full,
+        };
+        let results: Vec<DriftResult> = config_names
+            .iter()
+            .map(|config| {
+                print_info(&format!("Checking {} configuration for drift...", config));
+                match self.check_drift(config, directory, template, full, extra) {
+                    Ok(result) => result,
+                    Err(err) => DriftResult {
+                        config: config.clone(),
+                        status: DriftStatus::Failed(err.to_string()),
+                        changes: vec![],
+                    },
+                }
+            })
+            .collect();
+
+        match format.as_str() {
+            "json" => println!("{}", format_drift_json(&results)),
+            "junit" => println!(
+                "{}",
+                format_junit(DRIFT_REPORT_NAME, &get_drift_report_cases(&results))
+            ),
+            _ => {
+                let mut rows = Vec::new();
+                results.iter().for_each(|result| {
+                    if let DriftStatus::Failed(message) = &result.status {
+                        print_warning(&format!("[{}] {}", result.config, message));
+                    }
+                    rows.push(vec![
+                        result.config.clone(),
+                        result.get_status_name().to_string(),
+                        String::new(),
+                        String::new(),
+                    ]);
+                    result.changes.iter().for_each(|change| {
+                        rows.push(vec![
+                            String::new(),
+                            String::new(),
+                            change.address.clone(),
+                            change.action.to_string(),
+                        ])
+                    });
+                });
+                print_table(&["CONFIG", "STATUS", "RESOURCE", "ACTION"], &rows);
+            }
+        };
+        write_reports(
+            report,
+            DRIFT_REPORT_NAME,
+            &get_drift_report_cases(&results),
+            &vec![],
+        )?;
+
+        let drifted_count = results
+            .iter()
+            .filter(|result| result.status == DriftStatus::Drifted)
+            .count();
+        let failed_count = results
+            .iter()
+            .filter(|result| matches!(result.status, DriftStatus::Failed(_)))
+            .count();
+        match (drifted_count, failed_count) {
+            (0, 0) => Ok(()),
+            (0, count) => Err(SageError::DriftCheckFailed(count)),
+            (count, _) => Err(SageError::DriftDetected(count)),
+        }
+    }
+
+    // Runs the plan with the detailed exit code for the configuration with
+    // `config` name, without locking the state. The exit code 2 means that the
+    // infrastructure differs from the state, and then the drifted resources
+    // are read from the saved plan.
+    fn check_drift(
+        &self,
+        config: &String,
+        directory: &String,
+        template: &String,
+        full: bool,
+        extra: &Vec<String>,
+    ) -> Result<DriftResult, SageError> {
+        let configs = get_configs(directory)?;
+        let configs_path = configs.get(config).unwrap();
+        let out_filename = Some(String::from("main.tf"));
+        let main_filepath = self.get_main_tf(directory, config, &None, template, &out_filename)?;
+        let mut terraform_args = self
+            .terraform
+            .get_command_args(configs_path, directory, extra);
+        let mut drift_args = vec![DETAILED_EXITCODE_ARG, NO_LOCK_ARG, NO_INPUT_ARG];
+        if !full {
+            drift_args.push(REFRESH_ONLY_ARG);
+        }
+        drift_args
+            .iter()
+            .rev()
+            .for_each(|arg| terraform_args.insert(0, arg.to_string()));
+        let plan_file = self.prepare_plan_file(directory, config, &mut terraform_args)?;
+        let context = CommandContext::new(directory, config, Some(&main_filepath))?;
+        let exit_code =
+            self.terraform
+                .call_with_exit_code("plan", &terraform_args, &context, &[0, 2])?;
+
+        let changes = match exit_code {
+            0 => Ok(vec![]),
+            _ => get_plan_drift(directory, config, &plan_file),
+        };
+        self.delete_saved_plan(directory, config);
+        let status = match exit_code {
+            0 => DriftStatus::InSync,
+            _ => DriftStatus::Drifted,
+        };
+        Ok(DriftResult {
+            config: config.clone(),
+            status,
+            changes: changes?,
+        })
+    }
+
     // Returns a path to the used Terraform main.tf module.
     //
     // If `target` option contains path to *.tf module, then it will be
@@ -282,33 +1130,846 @@ impl SageClient {
         out: &Option<String>,
     ) -> Result<String, SageError> {
         match target {
-            Some(file_name) => Ok(file_name.to_owned()),
+            Some(file_name) => {
+                if let Some(reason) = get_outdated_reason(file_name) {
+                    print_warning(&format!("The target module is outdated: {}.", reason));
+                }
+                Ok(file_name.to_owned())
+            }
             None => {
-                print_warning("The `target` option was not specified.");
                 let out_file_name = out.clone().unwrap_or(generate_file_name(config));
                 self.generate_main_tf(directory, config, template, &out_file_name)
             }
         }
     }
 
-    // Prints all available configurations, stored by path in `directory` parameter.
-    fn show_configurations(&self, directory: &String) -> Result<(), SageError> {
+    // Checks the local git repository against the rules of the environment
+    // sections, matching the configuration with `config` name, before the
+    // command with `command` name changes the infrastructure. Each failed rule
+    // is printed.
+    fn check_git_rules(
+        &self,
+        command: &str,
+        config: &String,
+        directory: &String,
+    ) -> Result<(), SageError> {
+        let project = ProjectConfig::load(directory)?;
+        let violations: Vec<GitRuleViolation> = project
+            .get_git_rules(config)
+            .into_iter()
+            .flat_map(|rules| check_git_rules(directory, rules))
+            .collect();
+        if violations.is_empty() {
+            return Ok(());
+        }
+
+        violations.iter().for_each(|violation| {
+            print_warning(&format!(
+                "The `{}` git rule failed: {}",
+                violation.rule, violation.message
+            ))
+        });
+        Err(SageError::GitRulesViolated(
+            command.to_string(),
+            config.clone(),
+            violations.len(),
+        ))
+    }
+
+    // Runs the `action` of the command with `command` name for the configuration
+    // with `config` name between the before and after hooks, declared in the
+    // project settings in the directory specified in `directory` parameter.
+    // The failed hook aborts the command. When the command or any of its hooks
+    // has failed, then the on_failure hooks are called.
+    fn run_with_hooks<F>(
+        &self,
+        command: &str,
+        config: &String,
+        directory: &String,
+        stack: Option<&String>,
+        action: F,
+    ) -> Result<(), SageError>
+    where
+        F: FnOnce() -> Result<(), SageError>,
+    {
+        let project = ProjectConfig::load(directory)?;
+        let mut context = HookContext {
+            command: command.to_string(),
+            config: config.clone(),
+            directory: directory.clone(),
+            stack: stack.cloned(),
+            exit_code: 0,
+            error: None,
+        };
+
+        let before_hook = format!("{}{}", BEFORE_HOOK_PREFIX, command);
+        let after_hook = format!("{}{}", AFTER_HOOK_PREFIX, command);
+        let result = run_hooks(
+            &project.get_hooks(config, &before_hook),
+            &before_hook,
+            &context,
+        )
+        .and_then(|_| action())
+        .and_then(|_| {
+            run_hooks(
+                &project.get_hooks(config, &after_hook),
+                &after_hook,
+                &context,
+            )
+        });
+
+        if let Err(err) = &result {
+            context.exit_code = match err {
+                SageError::TerraformFailed(_, Some(code), _) => *code,
+                _ => 1,
+            };
+            context.error = Some(err.to_string());
+            let hooks = project.get_hooks(config, ON_FAILURE_HOOK);
+            if let Err(hook_err) = run_hooks(&hooks, ON_FAILURE_HOOK, &context) {
+                print_warning(&hook_err.to_string());
+            }
+        }
+        result
+    }
+
+    // Runs the plan or apply command for the configuration with `config` name
+    // in each of the project stacks, selected in `stacks` parameter. Stacks
+    // are processed in the order of their dependencies, the execution stops
+    // on the first failed stack. The results are printed per stack. Each stack
+    // is initialized before the command, because Terraform is executed in the
+    // stack directory with its own backend and `.terraform` directory.
+    fn run_stacks(
+        &self,
+        command: &Command,
+        config: &String,
+        directory: &String,
+        stacks: &String,
+        template: &String,
+        out: &Option<String>,
+        cleanup: bool,
+        extra: &Vec<String>,
+    ) -> Result<(), SageError> {
+        let project = ProjectConfig::load(directory)?;
+        let order = project.get_stacks_order(stacks)?;
+        let mut plan_report = Report::default();
+        if let Command::Apply { .. } = command {
+            self.check_git_rules("apply", config, directory)?;
+        }
+
+        let init_extra = vec![String::new(), NO_INPUT_ARG.to_string()];
+        let mut rows = Vec::new();
+        let mut failure = None;
+        for stack in order.iter() {
+            let stack_directory = project.get_stack_directory(directory, stack);
+            if failure.is_some() {
+                rows.push(vec![
+                    stack.clone(),
+                    stack_directory,
+                    String::from("skipped"),
+                ]);
+                continue;
+            }
+
+            print_info(&format!("Processing {} stack...", stack));
+            let result = match command {
+                Command::Plan { summary_only, .. } => {
+                    self.run_with_hooks("plan", config, directory, Some(stack), || {
+                        self.init_terraform(
+                            config,
+                            &stack_directory,
+                            &None,
+                            template,
+                            out,
+                            false,
+                            &init_extra,
+                        )?;
+                        self.plan_changes(
+                            config,
+                            &stack_directory,
+                            &None,
+                            template,
+                            out,
+                            *summary_only,
+                            Some(stack),
+                            &mut plan_report,
+                            cleanup,
+                            extra,
+                        )
+                    })
+                }
+                Command::Apply {
+                    policy,
+                    allow_deletes,
+                    ..
+                } => self.run_with_hooks("apply", config, directory, Some(stack), || {
+                    self.init_terraform(
+                        config,
+                        &stack_directory,
+                        &None,
+                        template,
+                        out,
+                        false,
+                        &init_extra,
+                    )?;
+                    self.apply_changes(
+                        config,
+                        &stack_directory,
+                        &None,
+                        template,
+                        out,
+                        policy,
+                        *allow_deletes,
+                        cleanup,
+                        extra,
+                    )
+                }),
+                _ => unreachable!(),
+            };
+            let status = match result {
+                Ok(_) => String::from("succeeded"),
+                Err(err) => {
+                    let status = format!("failed: {}", err);
+                    failure = Some(err);
+                    status
+                }
+            };
+            rows.push(vec![stack.clone(), stack_directory, status]);
+        }
+
+        print_table(&["STACK", "PATH", "RESULT"], &rows);
+        if let Command::Plan { report, .. } = command {
+            if !report.is_empty() {
+                write_reports(
+                    report,
+                    PLAN_REPORT_NAME,
+                    &plan_report.cases,
+                    &plan_report.sections,
+                )?;
+            }
+        }
+        match failure {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    // Prints the latest records of the audit log, optionally only for the
+    // configuration with `config` name. The oldest of the shown records is
+    // printed first.
+    fn show_history(
+        &self,
+        config: &Option<String>,
+        directory: &String,
+        limit: usize,
+        format: &String,
+    ) -> Result<(), SageError> {
+        let audit_log_path = get_audit_log_path(directory)?;
+        let mut records = read_audit_log(&audit_log_path)?;
+        if let Some(config) = config {
+            records.retain(|record| &record.config == config);
+        }
+        let records = records.split_off(records.len().saturating_sub(limit));
+
+        match format.as_str() {
+            "json" => {
+                let document = serde_json::to_string_pretty(&records).unwrap_or_default();
+                println!("{}", document);
+            }
+            _ => {
+                let rows: Vec<Vec<String>> = records
+                    .iter()
+                    .map(|record| {
+                        let commit = match (&record.git_commit, record.git_dirty) {
+                            (Some(commit), Some(true)) => format!("{:.8} (dirty)", commit),
+                            (Some(commit), _) => format!("{:.8}", commit),
+                            (None, _) => String::new(),
+                        };
+                        let exit_code = match record.exit_code {
+                            Some(code) => code.to_string(),
+                            None => String::from("-"),
+                        };
+                        vec![
+                            record.timestamp.clone(),
+                            record.user.clone(),
+                            record.config.clone(),
+                            format!("{} {}", record.command, record.args.join(" ")),
+                            exit_code,
+                            format!("{:.1}s", record.duration_ms as f64 / 1000.0),
+                            commit,
+                        ]
+                    })
+                    .collect();
+                print_table(
+                    &[
+                        "TIME", "USER", "CONFIG", "COMMAND", "EXIT", "DURATION", "COMMIT",
+                    ],
+                    &rows,
+                );
+            }
+        };
+        Ok(())
+    }
+
+    // Prints all available configurations, stored by path in `directory` parameter,
+    // with their metadata. When the `tag` is specified, then prints only the
+    // configurations marked with this tag.
+    fn show_configurations(
+        &self,
+        directory: &String,
+        tag: &Option<String>,
+    ) -> Result<(), SageError> {
         let configs = get_configs(directory)?;
+        let mut config_names: Vec<&String> = configs.keys().collect();
+        config_names.sort();
+        let project = ProjectConfig::load(directory)?;
+        let data_per_environment = project.terraform.data_per_environment;
 
-        match configs.len() {
-            count if count > 0 => {
-                print_info("Available configurations:");
-                configs
-                    .keys()
-                    .into_iter()
-                    .for_each(|config| print_info(&format!("- {}", config)));
+        let mut rows = Vec::new();
+        for config in config_names {
+            let metadata = get_config_metadata(directory, config, &configs[config])?;
+            if let Some(tag) = tag {
+                if !metadata.tags.contains(tag) {
+                    continue;
+                }
             }
+
+            let variable_files = self.terraform.get_config_variable_files(&configs[config]);
+            let format_flag = |flag: bool| String::from(if flag { "yes" } else { "no" });
+            let mut row = vec![
+                config.clone(),
+                metadata.description,
+                metadata.owner,
+                format_flag(metadata.protected),
+                variable_files.len().to_string(),
+            ];
+            if data_per_environment {
+                let data_directory = get_data_directory(directory, config);
+                row.push(format_flag(Path::new(&data_directory).exists()));
+            }
+            rows.push(row);
+        }
+
+        let mut headers = vec!["NAME", "DESCRIPTION", "OWNER", "PROTECTED", "VAR FILES"];
+        if data_per_environment {
+            headers.push("INITIALIZED");
+        }
+        match rows.len() {
+            count if count > 0 => print_table(&headers, &rows),
             _ => print_warning("Configurations were not found."),
         };
 
         Ok(())
     }
 
+    // Prints the resolved template context for the configuration with `config`
+    // name in the requested `format`. When `show_source` is set, each key is
+    // annotated with the file and the section it came from.
+    fn show_context(
+        &self,
+        config: &String,
+        directory: &String,
+        format: &String,
+        show_source: bool,
+    ) -> Result<(), SageError> {
+        let configs = get_configs(directory)?;
+        is_correct_config(config, configs)?;
+        let mut context = get_template_context(directory, config)?;
+        resolve_stack_outputs(directory, config, &mut context)?;
+        println!("{}", format_context(&context, format, show_source));
+        Ok(())
+    }
+
+    // Prints the rendered Terraform main module to stdout without touching
+    // the files in the working directory.
+    fn print_main_tf(
+        &self,
+        directory: &String,
+        config: &String,
+        template: &String,
+    ) -> Result<(), SageError> {
+        let module = render(directory, config, template)?;
+        print!("{}", module);
+        Ok(())
+    }
+
+    // Checks the variable files of the configuration with `config` name (or
+    // all available configurations with the `all` flag) against the variables
+    // declared in the Terraform modules and prints the found problems.
+    fn check_variable_files(
+        &self,
+        config: &Option<String>,
+        directory: &String,
+        template: &String,
+        all: bool,
+    ) -> Result<(), SageError> {
+        let configs = get_configs(directory)?;
+        let config_names: Vec<String> = match (all, config) {
+            (false, Some(config)) => {
+                is_correct_config(config, configs.clone())?;
+                vec![config.clone()]
+            }
+            _ => {
+                let mut config_names: Vec<String> = configs.keys().cloned().collect();
+                config_names.sort();
+                config_names
+            }
+        };
+
+        let mut problems_count = 0;
+        for config in config_names.iter() {
+            let definitions = get_variable_definitions(directory, config, template)?;
+            let mut variable_files = get_auto_variable_files(directory);
+            variable_files.extend(self.terraform.get_config_variable_files(&configs[config]));
+            let assignments = get_variable_assignments(&variable_files)?;
+            let report = check_variables(&definitions, &assignments);
+
+            report.missing.iter().for_each(|name| {
+                print_warning(&format!(
+                    "[{}] Required variable `{}` has no value.",
+                    config, name
+                ))
+            });
+            report.unknown.iter().for_each(|(name, filename)| {
+                print_warning(&format!(
+                    "[{}] Variable `{}` assigned in {} is not declared.",
+                    config, name, filename
+                ))
+            });
+            report
+                .mismatched
+                .iter()
+                .for_each(|(name, variable_type, filename)| {
+                    print_warning(&format!(
+                        "[{}] Variable `{}` assigned in {} is not a {}.",
+                        config, name, filename, variable_type
+                    ))
+                });
+            if report.problems_count() == 0 {
+                print_info(&format!("[{}] Variables are correct.", config));
+            }
+            problems_count += report.problems_count();
+        }
+
+        match problems_count {
+            0 => Ok(()),
+            count => Err(SageError::VariablesCheckFailed(count)),
+        }
+    }
+
+    // Prints the table with the variables and the template context keys of two
+    // configurations, showing which values differ, exist only in one of them
+    // or are identical.
+    fn compare_configurations(
+        &self,
+        left: &String,
+        right: &String,
+        directory: &String,
+    ) -> Result<(), SageError> {
+        let configs = get_configs(directory)?;
+        is_correct_config(left, configs.clone())?;
+        is_correct_config(right, configs.clone())?;
+
+        let left_files = self.terraform.get_config_variable_files(&configs[left]);
+        let right_files = self.terraform.get_config_variable_files(&configs[right]);
+        let left_values = get_configuration_values(directory, left, &left_files)?;
+        let right_values = get_configuration_values(directory, right, &right_files)?;
+
+        let rows: Vec<Vec<String>> = compare_values(&left_values, &right_values)
+            .into_iter()
+            .map(|(key, left_value, right_value, difference)| {
+                let status = match difference {
+                    ValueDifference::Identical => String::from("identical"),
+                    ValueDifference::Different => String::from("different"),
+                    ValueDifference::OnlyLeft => format!("only in {}", left),
+                    ValueDifference::OnlyRight => format!("only in {}", right),
+                };
+                vec![
+                    key,
+                    left_value.unwrap_or_else(|| String::from("-")),
+                    right_value.unwrap_or_else(|| String::from("-")),
+                    status,
+                ]
+            })
+            .collect();
+
+        print_table(&["KEY", left, right, "STATUS"], &rows);
+        Ok(())
+    }
+
+    // Creates a new project with the template for the given `backend` in the
+    // directory specified in `directory` parameter.
+    fn create_project(&self, directory: &String, backend: &String) -> Result<(), SageError> {
+        let files = create_project(directory, backend)?;
+        print_info(&format!("New project was created by path: {}", directory));
+        files
+            .iter()
+            .for_each(|file| print_info(&format!("- {}", file)));
+        Ok(())
+    }
+
+    // Creates a new configuration with the `name` name. With the `from` option
+    // copies the variable files and the context of the given configuration,
+    // including the ones of its parent directories.
+    fn add_configuration(
+        &self,
+        name: &String,
+        directory: &String,
+        from: &Option<String>,
+    ) -> Result<(), SageError> {
+        let variable_files = match from {
+            Some(from) => {
+                let configs = get_configs(directory)?;
+                is_correct_config(from, configs.clone())?;
+                self.terraform.get_config_variable_files(&configs[from])
+            }
+            None => vec![],
+        };
+
+        let config_path = add_environment(directory, name, from, &variable_files)?;
+        print_info(&format!(
+            "New configuration was created by path: {}",
+            config_path
+        ));
+        Ok(())
+    }
+
+    // Renames the configuration with `name` to `new_name`. The rename is refused
+    // when the backend, rendered from the template, depends on the name, unless
+    // the `force` flag is set.
+    fn rename_configuration(
+        &self,
+        name: &String,
+        new_name: &String,
+        directory: &String,
+        template: &String,
+        force: bool,
+    ) -> Result<(), SageError> {
+        let config_path = rename_environment(directory, name, new_name, template, force)?;
+        print_info(&format!(
+            "Configuration {} was renamed to {} by path: {}",
+            name, new_name, config_path
+        ));
+        print_warning(&format!(
+            "Call `terraform-sage init {}` before using the renamed configuration.",
+            new_name
+        ));
+        Ok(())
+    }
+
+    // Removes the configuration with `name`. The removal is refused while
+    // the Terraform state of the configuration is not empty, unless the
+    // `force` flag is set.
+    fn remove_configuration(
+        &self,
+        name: &String,
+        directory: &String,
+        template: &String,
+        force: bool,
+    ) -> Result<(), SageError> {
+        let configs = get_configs(directory)?;
+        is_correct_config(name, configs)?;
+        if !force {
+            self.check_empty_state(name, directory, template)?;
+        }
+
+        remove_environment(directory, name)?;
+        print_info(&format!("Configuration {} was removed.", name));
+        Ok(())
+    }
+
+    // Checks that the Terraform state of the configuration with `name` doesn't
+    // contain any resources. The main module of the configuration is generated
+    // in place of the main.tf file and initialized in a temporary data
+    // directory, so afterwards the main.tf file, the dependency lock file and
+    // the manifest are restored to their previous content.
+    fn check_empty_state(
+        &self,
+        name: &String,
+        directory: &String,
+        template: &String,
+    ) -> Result<(), SageError> {
+        let saved_files: Vec<(PathBuf, Option<String>)> = [
+            Path::new(directory).join("main.tf"),
+            Path::new(directory).join(LOCK_FILE_NAME),
+            Path::new(directory)
+                .join(SAGE_DIRECTORY_NAME)
+                .join(MANIFEST_FILE_NAME),
+        ]
+        .iter()
+        .map(|path| (path.clone(), fs::read_to_string(path).ok()))
+        .collect();
+
+        let result = self
+            .generate_main_tf(directory, name, template, &String::from("main.tf"))
+            .and_then(|main_filepath| self.count_state_resources(name, directory, &main_filepath));
+
+        for (path, content) in saved_files.iter() {
+            let filepath = path.to_string_lossy().into_owned();
+            match content {
+                Some(content) => {
+                    write_file_atomically(&filepath, content)?;
+                }
+                None if path.exists() => fs::remove_file(path).context(&filepath)?,
+                None => {}
+            }
+        }
+
+        match result? {
+            0 => Ok(()),
+            count => {
+                let message = format!(
+                    "The state of the {} configuration still has {} resource(s). Destroy them first or use the --force option.",
+                    name, count
+                );
+                Err(SageError::InvalidConfig(message))
+            }
+        }
+    }
+
+    // Returns the number of resources in the Terraform state of the configuration
+    // with `name`, which main module is saved by `main_filepath` path. Terraform
+    // is executed in the project directory, like for the apply command, but it
+    // is initialized in a temporary data directory, which is deleted afterwards.
+    // The state, which doesn't exist or can't be read, is reported as error,
+    // because it can't be proven empty.
+    fn count_state_resources(
+        &self,
+        name: &String,
+        directory: &String,
+        main_filepath: &String,
+    ) -> Result<usize, SageError> {
+        let data_directory = env::temp_dir()
+            .join(format!("terraform-sage-state-{}", process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let mut context = CommandContext::new(directory, name, Some(main_filepath))?;
+        context.data_directory = Some(data_directory.clone());
+
+        let init_args = vec![NO_INPUT_ARG.to_string()];
+        let state_args = vec![String::from("pull")];
+        let result = self
+            .terraform
+            .call_with_output("init", &init_args, &context)
+            .and_then(|_| {
+                self.terraform
+                    .call_with_output("state", &state_args, &context)
+            });
+        if Path::new(&data_directory).exists() {
+            fs::remove_dir_all(&data_directory).context(&data_directory)?;
+        }
+
+        result
+            .map_err(|err| err.to_string())
+            .and_then(|output| match output.trim() {
+                "" => Err(String::from("the state doesn't exist")),
+                output => serde_json::from_str::<JsonValue>(output)
+                    .map_err(|err| format!("the state is invalid: {}", err))?["resources"]
+                    .as_array()
+                    .map(|resources| resources.len())
+                    .ok_or_else(|| String::from("the state doesn't list the resources")),
+            })
+            .map_err(|reason| {
+                let message = format!(
+                    "Unable to check the state of the {} configuration ({}). Use the --force option to remove it anyway.",
+                    name, reason
+                );
+                SageError::InvalidConfig(message)
+            })
+    }
+
+    // Checks the template placeholders against the context of each available
+    // configuration and prints the found problems.
+    fn lint_template(
+        &self,
+        directory: &String,
+        template: &String,
+        report_targets: &Vec<ReportTarget>,
+    ) -> Result<(), SageError> {
+        let report = lint_project(directory, template)?;
+        write_reports(
+            report_targets,
+            LINT_REPORT_NAME,
+            &report.get_report_cases(),
+            &vec![],
+        )?;
+
+        report.missing_keys.iter().for_each(|(config, keys)| {
+            print_warning(&format!(
+                "Placeholders not defined for the {} configuration: {}",
+                config,
+                keys.join(", ")
+            ))
+        });
+        report.unused_keys.iter().for_each(|(config, keys)| {
+            print_warning(&format!(
+                "Keys of the {} configuration never used in the template: {}",
+                config,
+                keys.join(", ")
+            ))
+        });
+        if !report.unused_defaults.is_empty() {
+            print_warning(&format!(
+                "Keys of the [{}] section never used by any configuration: {}",
+                DEFAULTS_SECTION_NAME,
+                report.unused_defaults.join(", ")
+            ))
+        }
+        report.unknown_sections.iter().for_each(|section| {
+            print_warning(&format!(
+                "The [{}] section doesn't have a matching configuration directory.",
+                section
+            ))
+        });
+
+        match report.problems_count() {
+            0 => Ok(()),
+            count => Err(SageError::LintFailed(count)),
+        }
+    }
+
+    // Generates a new Terraform main module for the `generate` command.
+    //
+    // With the `show_diff` flag prints a unified diff between the existing file
+    // and the rendered template. With the `check` flag the file is left as is
+    // and an error is returned if it differs from the rendered template. Files
+    // that weren't generated by terraform-sage are overwritten only with `force`.
+    fn update_main_tf(
+        &self,
+        directory: &String,
+        config: &String,
+        template: &String,
+        out: &String,
+        show_diff: bool,
+        check: bool,
+        force: bool,
+    ) -> Result<(), SageError> {
+        let configs = get_configs(directory)?;
+        is_correct_config(config, configs)?;
+        let used_directory = Path::new(directory);
+        let path_to_target = used_directory.join(template).to_string_lossy().into_owned();
+        let path_to_out = used_directory.join(out).to_string_lossy().into_owned();
+
+        if show_diff || check {
+            let module = render_from_template(directory, config, &path_to_target, true)?;
+            let current = fs::read_to_string(&path_to_out).unwrap_or_default();
+
+            if show_diff {
+                print!("{}", diff_rendered_file(&path_to_out, &current, &module));
+            }
+
+            if check {
+                return match current == module {
+                    true => Ok(()),
+                    false => Err(SageError::OutdatedFile(path_to_out)),
+                };
+            }
+        }
+
+        generate_from_template(directory, config, &path_to_target, &path_to_out, force)?;
+        Ok(())
+    }
+
+    // Renders all available configurations into the `out_dir` directory, where
+    // each configuration gets its own sub-directory with the generated module
+    // and copies of the variable files.
+    //
+    // When the `diff_against` option contains a path to the directory with the
+    // previously rendered configurations (e.g. for another git revision), then
+    // prints which configurations were changed in comparison with it.
+    fn generate_all_main_tf(
+        &self,
+        directory: &String,
+        template: &String,
+        out: &String,
+        out_dir: &String,
+        diff_against: &Option<String>,
+        force: bool,
+    ) -> Result<(), SageError> {
+        if let Some(base_dir) = diff_against.as_ref().filter(|d| !Path::new(d).is_dir()) {
+            let message = format!("The {} directory doesn't exist.", base_dir);
+            return Err(SageError::InvalidConfig(message));
+        }
+
+        let configs = get_configs(directory)?;
+        let mut config_names: Vec<&String> = configs.keys().collect();
+        config_names.sort();
+        let path_to_target = Path::new(directory)
+            .join(template)
+            .to_string_lossy()
+            .into_owned();
+        // The generated files are tracked by the manifest of the project, so
+        // that the build directory contains only the files to be shipped.
+        let mut manifest = Manifest::load(Path::new(directory));
+
+        for config in config_names.iter() {
+            let config_out_dir = Path::new(out_dir).join(config);
+            let config_out_dir_path = config_out_dir.to_string_lossy().into_owned();
+            fs::create_dir_all(&config_out_dir).context(&config_out_dir_path)?;
+
+            let path_to_out = config_out_dir.join(out).to_string_lossy().into_owned();
+            generate_tracked_file(
+                directory,
+                config,
+                &path_to_target,
+                &path_to_out,
+                force,
+                &mut manifest,
+            )?;
+
+            // Files from the parent directories of the nested configuration are
+            // prefixed with the directory name to avoid the name collisions.
+            for var_file in self.terraform.get_config_variable_files(&configs[*config]) {
+                let var_file_path = Path::new(&var_file);
+                let mut file_name = var_file_path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned();
+                if var_file_path.parent() != Some(Path::new(&configs[*config])) {
+                    let parent_name = var_file_path
+                        .parent()
+                        .and_then(|parent| parent.file_name())
+                        .unwrap_or_default()
+                        .to_string_lossy();
+                    file_name = format!("{}-{}", parent_name, file_name);
+                }
+                let path_to_copy = config_out_dir
+                    .join(file_name)
+                    .to_string_lossy()
+                    .into_owned();
+                let content = fs::read_to_string(&var_file).context(&var_file)?;
+                write_file_atomically(&path_to_copy, &content)?;
+            }
+        }
+
+        if let Some(base_dir) = diff_against {
+            print_info(&format!("Changes in comparison with {}:", base_dir));
+            let mut base_configs: Vec<String> = get_leaf_directories(base_dir)
+                .into_keys()
+                .filter(|name| !configs.contains_key(name))
+                .collect();
+            base_configs.sort();
+
+            for config in config_names.iter() {
+                let current = Path::new(out_dir).join(config);
+                let previous = Path::new(base_dir).join(config);
+                let status = match previous.is_dir() {
+                    false => String::from("added"),
+                    true => match get_different_files(&current, &previous) {
+                        files if files.is_empty() => String::from("unchanged"),
+                        files => format!("changed ({})", files.join(", ")),
+                    },
+                };
+                print_info(&format!("- {}: {}", config, status));
+            }
+            base_configs
+                .iter()
+                .for_each(|config| print_info(&format!("- {}: removed", config)));
+        }
+
+        Ok(())
+    }
+
     // Generates a new Terraform main module.
     //
     // It used the directory, specified in `directory` parameter as the main
@@ -327,6 +1988,74 @@ impl SageClient {
         let used_directory = Path::new(directory);
         let path_to_target = used_directory.join(template).to_string_lossy().into_owned();
         let path_to_out = used_directory.join(out).to_string_lossy().into_owned();
-        generate_from_template(directory, config, &path_to_target, &path_to_out)
+        generate_from_template(directory, config, &path_to_target, &path_to_out, false)
+    }
+}
+
+// Checks that the command prints machine-readable data (e.g. JSON documents or
+// the rendered module) to stdout, so the messages must not be mixed with it.
+fn prints_data(command: &Command) -> bool {
+    match command {
+        Command::Context(ContextCommand::Show { .. }) => true,
+        Command::Generate { stdout, .. } => *stdout,
+        Command::Output { format, raw, .. } => raw.is_some() || format != "table",
+        Command::Drift { format, .. } => format != "text",
+        Command::History { format, .. } => format != "table",
+        _ => false,
+    }
+}
+
+// Returns the configurations, which are passed to the command by name, with
+// the directories of their projects.
+fn get_named_configs(command: &Command) -> Vec<(&String, &String)> {
+    match command {
+        Command::Init {
+            config, directory, ..
+        }
+        | Command::Plan {
+            config, directory, ..
+        }
+        | Command::Apply {
+            config, directory, ..
+        }
+        | Command::Destroy {
+            config, directory, ..
+        }
+        | Command::Output {
+            config, directory, ..
+        }
+        | Command::Context(ContextCommand::Show {
+            config, directory, ..
+        })
+        | Command::Policy(PolicyCommand::Check {
+            config, directory, ..
+        }) => vec![(config, directory)],
+        Command::Generate {
+            config: Some(config),
+            directory,
+            ..
+        }
+        | Command::Validate {
+            config: Some(config),
+            directory,
+            ..
+        }
+        | Command::Drift {
+            config: Some(config),
+            directory,
+            ..
+        }
+        | Command::Vars(VarsCommand::Check {
+            config: Some(config),
+            directory,
+            ..
+        }) => vec![(config, directory)],
+        Command::Vars(VarsCommand::Diff {
+            left,
+            right,
+            directory,
+            ..
+        }) => vec![(left, directory), (right, directory)],
+        _ => vec![],
     }
 }
@@ -0,0 +1,149 @@
+use serde_json::{json, Value as JsonValue};
+
+use crate::plan::ResourceChange;
+use crate::report::{ReportCase, ReportOutcome};
+
+pub const DRIFT_REPORT_NAME: &'static str = "drift";
+
+// State of the configuration, compared to the real infrastructure.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DriftStatus {
+    InSync,
+    Drifted,
+    Failed(String),
+}
+
+// Result of the drift check for the configuration. The changes are the
+// resources, which were changed outside of Terraform or differ from the
+// configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DriftResult {
+    pub config: String,
+    pub status: DriftStatus,
+    pub changes: Vec<ResourceChange>,
+}
+
+impl DriftResult {
+    // Returns the name of the status for printing.
+    pub fn get_status_name(&self) -> &'static str {
+        match self.status {
+            DriftStatus::InSync => "in sync",
+            DriftStatus::Drifted => "drifted",
+            DriftStatus::Failed(_) => "failed",
+        }
+    }
+}
+
+// Formats the results of the drift check as JSON document.
+pub fn format_drift_json(results: &Vec<DriftResult>) -> String {
+    let document: Vec<JsonValue> = results
+        .iter()
+        .map(|result| {
+            let resources: Vec<JsonValue> = result
+                .changes
+                .iter()
+                .map(|change| {
+                    json!({
+                        "address": change.address,
+                        "type": change.resource_type,
+                        "action": change.action.to_string(),
+                    })
+                })
+                .collect();
+            let error = match &result.status {
+                DriftStatus::Failed(message) => JsonValue::String(message.clone()),
+                _ => JsonValue::Null,
+            };
+            json!({
+                "config": result.config,
+                "status": result.get_status_name(),
+                "resources": resources,
+                "error": error,
+            })
+        })
+        .collect();
+    serde_json::to_string_pretty(&document).unwrap_or_default()
+}
+
+// Converts the results of the drift check to the report cases, one case per
+// configuration.
+pub fn get_drift_report_cases(results: &Vec<DriftResult>) -> Vec<ReportCase> {
+    results
+        .iter()
+        .map(|result| {
+            let outcome = match &result.status {
+                DriftStatus::InSync => ReportOutcome::Passed,
+                DriftStatus::Drifted => {
+                    let message = format!("{} resource(s) drifted", result.changes.len());
+                    ReportOutcome::Failed(message)
+                }
+                DriftStatus::Failed(message) => ReportOutcome::Error(message.clone()),
+            };
+            let details = result
+                .changes
+                .iter()
+                .map(|change| format!("{} ({})", change.address, change.action))
+                .collect();
+            ReportCase {
+                name: result.config.clone(),
+                group: DRIFT_REPORT_NAME.to_string(),
+                outcome,
+                details,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value as JsonValue};
+
+    use crate::drift::{format_drift_json, get_drift_report_cases, DriftResult, DriftStatus};
+    use crate::plan::{ChangeAction, ResourceChange};
+    use crate::report::ReportOutcome;
+
+    #[test]
+    fn test_format_drift_results() {
+        let results = vec![
+            DriftResult {
+                config: String::from("production"),
+                status: DriftStatus::Drifted,
+                changes: vec![ResourceChange {
+                    address: String::from("aws_instance.web"),
+                    resource_type: String::from("aws_instance"),
+                    action: ChangeAction::Update,
+                    before: JsonValue::Null,
+                    after: JsonValue::Null,
+                }],
+            },
+            DriftResult {
+                config: String::from("staging"),
+                status: DriftStatus::Failed(String::from("Backend not initialized")),
+                changes: vec![],
+            },
+        ];
+
+        let document: JsonValue = serde_json::from_str(&format_drift_json(&results)).unwrap();
+        assert_eq!(
+            document[0],
+            json!({
+                "config": "production",
+                "status": "drifted",
+                "resources": [{"address": "aws_instance.web", "type": "aws_instance", "action": "update"}],
+                "error": null,
+            })
+        );
+        assert_eq!(document[1]["error"], "Backend not initialized");
+
+        let cases = get_drift_report_cases(&results);
+        assert_eq!(
+            cases[0].outcome,
+            ReportOutcome::Failed(String::from("1 resource(s) drifted"))
+        );
+        assert_eq!(cases[0].details, vec!["aws_instance.web (update)"]);
+        assert_eq!(
+            cases[1].outcome,
+            ReportOutcome::Error(String::from("Backend not initialized"))
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use std::io;
 
-use handlebars::TemplateRenderError;
+use handlebars::{TemplateError, TemplateRenderError};
 use quick_error::quick_error;
 
 quick_error! {
@@ -11,9 +11,56 @@ quick_error! {
             context(source: &'a String, err: io::Error)
                 -> (err, source.to_string())
         }
+        ContextParse(filename: String, line: usize, column: usize, message: String) {
+            display("Failed to parse {} at line {}, column {}: {}", filename, line, column, message)
+        }
+        ConfigParse(filename: String, line: usize, column: usize, message: String) {
+            display("Failed to parse the {} settings file at line {}, column {}: {}", filename, line, column, message)
+        }
         InvalidConfig(message: String) {
             display("Invalid configuration: {}", message)
         }
+        OutdatedFile(filename: String) {
+            display("The {} file is not up to date with the template", filename)
+        }
+        UnmanagedFile(filename: String) {
+            display("The {} file wasn't generated by terraform-sage or was modified after generation. Delete the file or use the `generate` command with the --force option to overwrite it", filename)
+        }
+        VariablesCheckFailed(count: usize) {
+            display("Found {} problem(s) in the variable files", count)
+        }
+        LintFailed(count: usize) {
+            display("Lint found {} problem(s) in the template and the context", count)
+        }
+        PolicyViolations(count: usize) {
+            display("The plan violates the policy rules with {} change(s)", count)
+        }
+        DestroyPrevented(config: String, addresses: String) {
+            display("Deleting these resources is prevented for the {} configuration: {}", config, addresses)
+        }
+        ValidationFailed(count: usize) {
+            display("Validation failed for {} configuration(s)", count)
+        }
+        DriftDetected(count: usize) {
+            display("Drift detected in {} configuration(s)", count)
+        }
+        DriftCheckFailed(count: usize) {
+            display("Failed to check drift for {} configuration(s)", count)
+        }
+        HookFailed(hook: String, command: String, message: String) {
+            display("The {} hook `{}` failed: {}", hook, command, message)
+        }
+        GitRulesViolated(command: String, config: String, count: usize) {
+            display("The `{}` command is not allowed for `{}`: {} git rule(s) failed", command, config, count)
+        }
+        Cancelled(command: String) {
+            display("The `{}` command was cancelled", command)
+        }
+        TemplateParse(err: TemplateError, filename: String) {
+            display("Template parsing error for {} file: {}", filename, err)
+            context(filename: &'a String, err: TemplateError)
+                -> (err, filename.to_string())
+        }
         TemplateRender(err: TemplateRenderError, filename: String) {
             display("Template rendering error for {} file: {}", filename, err)
             context(filename: &'a String, err: TemplateRenderError)
@@ -24,5 +71,8 @@ quick_error! {
             context(command: &'a str, err: io::Error)
                 -> (err, command.to_owned())
         }
+        TerraformFailed(command: String, exit_code: Option<i32>, message: String) {
+            display("Terraform `{}` command failed: {}", command, message)
+        }
     }
 }
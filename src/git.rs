@@ -0,0 +1,314 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use serde::Deserialize;
+
+use crate::audit::get_audit_log_path;
+use crate::manifest::{Manifest, SAGE_DIRECTORY_NAME};
+use crate::utils::matches_pattern;
+
+pub const REQUIRE_CLEAN_TREE_RULE: &'static str = "require_clean_tree";
+pub const ALLOWED_BRANCHES_RULE: &'static str = "allowed_branches";
+pub const REQUIRE_UP_TO_DATE_WITH_RULE: &'static str = "require_up_to_date_with";
+
+// State of the git repository, which contains the project.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitStatus {
+    pub commit: String,
+    pub dirty: bool,
+}
+
+// Rules for the local git repository, which must be satisfied before the
+// changes of the environment are applied or destroyed. Branches are glob
+// patterns, the reference to be up to date with is any local git reference,
+// e.g. `origin/main`, so it's never fetched.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitRules {
+    pub require_clean_tree: bool,
+    pub allowed_branches: Vec<String>,
+    pub require_up_to_date_with: Option<String>,
+}
+
+impl GitRules {
+    // Returns true, if none of the rules is set.
+    pub fn is_empty(&self) -> bool {
+        !self.require_clean_tree
+            && self.allowed_branches.is_empty()
+            && self.require_up_to_date_with.is_none()
+    }
+}
+
+// Describes the git rule, which isn't satisfied by the local repository.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitRuleViolation {
+    pub rule: String,
+    pub message: String,
+}
+
+impl GitRuleViolation {
+    fn new(rule: &str, message: String) -> GitRuleViolation {
+        GitRuleViolation {
+            rule: rule.to_string(),
+            message,
+        }
+    }
+}
+
+// Checks the git repository, which contains the directory specified in
+// `directory` parameter, against the rules. Only the local repository is
+// inspected. Returns the violated rules.
+pub fn check_git_rules(directory: &String, rules: &GitRules) -> Vec<GitRuleViolation> {
+    if rules.is_empty() {
+        return vec![];
+    }
+    if call_git(directory, &["rev-parse", "--git-dir"]).is_none() {
+        let message = format!("The {} directory isn't a git repository.", directory);
+        return vec![GitRuleViolation::new("git", message)];
+    }
+
+    let mut violations = Vec::new();
+    if rules.require_clean_tree {
+        let message = match get_uncommitted_changes(directory) {
+            Some(changes) => match changes.len() {
+                0 => None,
+                count => Some(format!(
+                    "The working tree has {} uncommitted change(s).",
+                    count
+                )),
+            },
+            None => Some(String::from(
+                "Unable to read the status of the working tree.",
+            )),
+        };
+        if let Some(message) = message {
+            violations.push(GitRuleViolation::new(REQUIRE_CLEAN_TREE_RULE, message));
+        }
+    }
+
+    if !rules.allowed_branches.is_empty() {
+        let allowed = rules.allowed_branches.join(", ");
+        match call_git(directory, &["symbolic-ref", "--quiet", "--short", "HEAD"]) {
+            Some(branch)
+                if rules
+                    .allowed_branches
+                    .iter()
+                    .any(|pattern| matches_pattern(pattern, &branch)) => {}
+            Some(branch) => {
+                let message = format!(
+                    "The `{}` branch is checked out, but only {} are allowed.",
+                    branch, allowed
+                );
+                violations.push(GitRuleViolation::new(ALLOWED_BRANCHES_RULE, message));
+            }
+            None => {
+                let message = format!("HEAD is detached, but only {} are allowed.", allowed);
+                violations.push(GitRuleViolation::new(ALLOWED_BRANCHES_RULE, message));
+            }
+        }
+    }
+
+    if let Some(reference) = &rules.require_up_to_date_with {
+        let range = format!("HEAD..{}", reference);
+        let commit = format!("{}^{{commit}}", reference);
+        let message = match call_git(directory, &["rev-parse", "--verify", "--quiet", &commit]) {
+            None => Some(format!(
+                "The `{}` reference doesn't exist in the local repository.",
+                reference
+            )),
+            Some(_) => match call_git(directory, &["rev-list", "--count", &range]).as_deref() {
+                Some("0") => None,
+                Some(count) => Some(format!(
+                    "HEAD is behind `{}` by {} commit(s).",
+                    reference, count
+                )),
+                None => Some(format!("Unable to compare HEAD with `{}`.", reference)),
+            },
+        };
+        if let Some(message) = message {
+            violations.push(GitRuleViolation::new(REQUIRE_UP_TO_DATE_WITH_RULE, message));
+        }
+    }
+    violations
+}
+
+// Returns the state of the git repository for the directory specified in
+// `directory` parameter, or None when the directory isn't under git or git
+// isn't installed.
+pub fn get_git_status(directory: &String) -> Option<GitStatus> {
+    let commit = call_git(directory, &["rev-parse", "HEAD"])?;
+    let changes = get_uncommitted_changes(directory)?;
+    Some(GitStatus {
+        commit,
+        dirty: !changes.is_empty(),
+    })
+}
+
+// Returns the paths of the files with uncommitted changes, including the
+// untracked files, in the git repository of the project in the directory
+// specified in `directory` parameter, or None when the status can't be read.
+// The files written by terraform-sage itself on every run are skipped: the
+// files in the `.sage` directories, the audit log and the generated modules,
+// tracked by the manifests.
+fn get_uncommitted_changes(directory: &String) -> Option<Vec<String>> {
+    let root = call_git(directory, &["rev-parse", "--show-toplevel"])?;
+    let args = ["status", "--porcelain", "-z", "--untracked-files=all"];
+    let status = call_git_untrimmed(directory, &args)?;
+    let audit_log = get_audit_log_path(directory)
+        .ok()
+        .and_then(|path| fs::canonicalize(path).ok());
+
+    let mut entries = status.split('\0').filter(|entry| entry.len() > 3);
+    let mut changes = Vec::new();
+    while let Some(entry) = entries.next() {
+        let (code, path) = entry.split_at(3);
+        if code.starts_with('R') || code.starts_with('C') {
+            entries.next();
+        }
+
+        let filepath = Path::new(&root).join(path);
+        let is_sage_directory = filepath
+            .components()
+            .any(|component| component.as_os_str() == SAGE_DIRECTORY_NAME);
+        let is_audit_log = audit_log.is_some() && fs::canonicalize(&filepath).ok() == audit_log;
+        let filepath = filepath.to_string_lossy().into_owned();
+        let is_generated_file = Manifest::load_for(&filepath).get(&filepath).is_some();
+        if !is_sage_directory && !is_audit_log && !is_generated_file {
+            changes.push(path.to_string());
+        }
+    }
+    Some(changes)
+}
+
+// Invokes git with the given arguments in the directory specified in
+// `directory` parameter. Returns the trimmed output of the command, or None
+// when the command has failed.
+fn call_git(directory: &String, args: &[&str]) -> Option<String> {
+    call_git_untrimmed(directory, args).map(|output| output.trim().to_string())
+}
+
+// Invokes git like `call_git`, but returns the output of the command as is.
+fn call_git_untrimmed(directory: &String, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(directory)
+        .stdin(Stdio::null())
+        .output()
+        .ok()?;
+
+    match output.status.success() {
+        true => Some(String::from_utf8_lossy(&output.stdout).into_owned()),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::process::Command;
+    use std::time::Duration;
+
+    use crate::audit::record_invocation;
+    use crate::git::{check_git_rules, GitRules};
+    use crate::template::generate_from_template;
+    use crate::terraform::CommandContext;
+    use crate::utils::create_test_directory;
+
+    #[test]
+    fn test_check_git_rules() {
+        let directory = create_test_directory("git-rules")
+            .to_string_lossy()
+            .into_owned();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(["-c", "user.name=sage", "-c", "user.email=sage@localhost"])
+                .args(args)
+                .current_dir(&directory)
+                .output()
+                .unwrap()
+                .status;
+            assert_eq!(status.success(), true);
+        };
+        git(&["init", "--quiet", "--initial-branch=main"]);
+        git(&["commit", "--quiet", "--allow-empty", "-m", "first"]);
+        git(&["branch", "upstream"]);
+        git(&["checkout", "--quiet", "upstream"]);
+        git(&["commit", "--quiet", "--allow-empty", "-m", "second"]);
+        git(&["checkout", "--quiet", "main"]);
+        fs::write(format!("{}/main.tf", directory), "").unwrap();
+
+        let rules = GitRules {
+            require_clean_tree: true,
+            allowed_branches: vec![String::from("release/*")],
+            require_up_to_date_with: Some(String::from("upstream")),
+        };
+        let violations = check_git_rules(&directory, &rules);
+        let passed = check_git_rules(
+            &directory,
+            &GitRules {
+                allowed_branches: vec![String::from("main")],
+                require_up_to_date_with: Some(String::from("main")),
+                ..GitRules::default()
+            },
+        );
+        fs::remove_dir_all(&directory).unwrap();
+
+        let messages: Vec<String> = violations
+            .iter()
+            .map(|violation| format!("{}: {}", violation.rule, violation.message))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "require_clean_tree: The working tree has 1 uncommitted change(s).",
+                "allowed_branches: The `main` branch is checked out, but only release/* are allowed.",
+                "require_up_to_date_with: HEAD is behind `upstream` by 1 commit(s).",
+            ]
+        );
+        assert_eq!(passed, vec![]);
+    }
+
+    #[test]
+    fn test_check_git_rules_skips_files_of_plan() {
+        let directory = create_test_directory("git-rules-plan")
+            .to_string_lossy()
+            .into_owned();
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(["-c", "user.name=sage", "-c", "user.email=sage@localhost"])
+                .args(args)
+                .current_dir(&directory)
+                .output()
+                .unwrap();
+        };
+        let template = format!("{}/main.tpl", directory);
+        fs::write(&template, "# {{CONFIG_NAME}}\n").unwrap();
+        fs::create_dir_all(format!("{}/configs/dev", directory)).unwrap();
+        fs::write(format!("{}/configs/dev/variables.tfvars", directory), "").unwrap();
+        git(&["init", "--quiet", "--initial-branch=main"]);
+        git(&["add", "."]);
+        git(&["commit", "--quiet", "-m", "first"]);
+
+        let config = String::from("dev");
+        let main_filepath = format!("{}/main.tf", directory);
+        generate_from_template(&directory, &config, &template, &main_filepath, false).unwrap();
+        let context = CommandContext::new(&directory, &config, Some(&main_filepath)).unwrap();
+        record_invocation("plan", &vec![], &context, Some(0), Duration::ZERO);
+        let rules = GitRules {
+            require_clean_tree: true,
+            ..GitRules::default()
+        };
+        let violations_after_plan = check_git_rules(&directory, &rules);
+        fs::write(
+            format!("{}/configs/dev/variables.tfvars", directory),
+            "a = 1",
+        )
+        .unwrap();
+        let violations_after_change = check_git_rules(&directory, &rules);
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(violations_after_plan, vec![]);
+        assert_eq!(violations_after_change.len(), 1);
+    }
+}
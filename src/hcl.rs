@@ -0,0 +1,679 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::ops::Range;
+use std::str::Chars;
+
+use crate::error::SageError;
+
+// Value of the attribute in Terraform configuration. Expressions that are
+// not literals (e.g. references, function calls or type constraints) are
+// kept in the raw form.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HclValue {
+    String(String),
+    Number(String),
+    Bool(bool),
+    Null,
+    List(Vec<HclValue>),
+    Map(Vec<(String, HclValue)>),
+    Expression(String),
+}
+
+impl fmt::Display for HclValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HclValue::String(value) => write!(f, "{:?}", value),
+            HclValue::Number(value) | HclValue::Expression(value) => write!(f, "{}", value),
+            HclValue::Bool(value) => write!(f, "{}", value),
+            HclValue::Null => write!(f, "null"),
+            HclValue::List(values) => {
+                let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+                write!(f, "[{}]", values.join(", "))
+            }
+            HclValue::Map(values) => {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(|(key, value)| format!("{} = {}", key, value))
+                    .collect();
+                write!(f, "{{{}}}", values.join(", "))
+            }
+        }
+    }
+}
+
+// Item of the body in Terraform configuration: an attribute assignment
+// (`name = value`) or a block (`type "label" { ... }`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum HclItem {
+    Attribute(String, HclValue),
+    Block(String, Vec<String>, Vec<HclItem>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    String(String),
+    Number(String),
+    Symbol(char),
+    Newline,
+}
+
+// Parses the content of Terraform configuration or variable files. The parser
+// supports only the subset of HCL, which is enough for extracting variable
+// definitions and assignments.
+pub fn parse_hcl(filename: &String, content: &str) -> Result<Vec<HclItem>, SageError> {
+    let (tokens, spans) =
+        tokenize(content).map_err(|message| get_parse_error(filename, message))?;
+    let mut parser = Parser {
+        content,
+        tokens,
+        spans,
+        position: 0,
+    };
+    parser
+        .parse_body(None)
+        .map_err(|message| get_parse_error(filename, message))
+}
+
+fn get_parse_error(filename: &String, message: String) -> SageError {
+    SageError::InvalidConfig(format!("Failed to parse {}: {}", filename, message))
+}
+
+// Iterator over the characters of the content, which tracks the byte offset
+// of the next character.
+struct Scanner<'a> {
+    chars: Peekable<Chars<'a>>,
+    offset: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn peek(&mut self) -> Option<&char> {
+        self.chars.peek()
+    }
+}
+
+impl<'a> Iterator for Scanner<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+}
+
+// Splits the content into tokens and returns them with their positions in the
+// content. Comments are skipped.
+fn tokenize(content: &str) -> Result<(Vec<Token>, Vec<Range<usize>>), String> {
+    let mut tokens = Vec::new();
+    let mut spans = Vec::new();
+    let mut chars = Scanner {
+        chars: content.chars().peekable(),
+        offset: 0,
+    };
+
+    while let Some(&c) = chars.peek() {
+        let start = chars.offset;
+        match c {
+            '\n' => {
+                chars.next();
+                tokens.push(Token::Newline);
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '#' => skip_line(&mut chars),
+            '/' => {
+                chars.next();
+                match chars.peek() {
+                    Some('/') => skip_line(&mut chars),
+                    Some('*') => {
+                        chars.next();
+                        skip_block_comment(&mut chars)?
+                    }
+                    _ => tokens.push(Token::Symbol('/')),
+                }
+            }
+            '"' => {
+                chars.next();
+                tokens.push(Token::String(read_string(&mut chars)?));
+            }
+            '<' => {
+                chars.next();
+                match chars.peek() {
+                    Some('<') => {
+                        chars.next();
+                        tokens.push(Token::String(read_heredoc(&mut chars)?));
+                    }
+                    _ => tokens.push(Token::Symbol('<')),
+                }
+            }
+            c if c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek() {
+                    match c.is_ascii_alphanumeric() || c == '.' {
+                        true => number.push(c),
+                        false => break,
+                    }
+                    chars.next();
+                }
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut identifier = String::new();
+                while let Some(&c) = chars.peek() {
+                    match c.is_alphanumeric() || c == '_' || c == '-' {
+                        true => identifier.push(c),
+                        false => break,
+                    }
+                    chars.next();
+                }
+                tokens.push(Token::Identifier(identifier));
+            }
+            c => {
+                chars.next();
+                tokens.push(Token::Symbol(c));
+            }
+        }
+        spans.resize(tokens.len(), start..chars.offset);
+    }
+
+    Ok((tokens, spans))
+}
+
+fn skip_line(chars: &mut Scanner) {
+    while let Some(&c) = chars.peek() {
+        if c == '\n' {
+            break;
+        }
+        chars.next();
+    }
+}
+
+fn skip_block_comment(chars: &mut Scanner) -> Result<(), String> {
+    while let Some(c) = chars.next() {
+        if c == '*' && chars.peek() == Some(&'/') {
+            chars.next();
+            return Ok(());
+        }
+    }
+    Err(String::from("unterminated comment"))
+}
+
+// Reads the quoted string. Interpolations (`${...}`) are kept as is.
+fn read_string(chars: &mut Scanner) -> Result<String, String> {
+    let mut value = String::new();
+    let mut depth = 0;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if depth == 0 => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some(escaped) => value.push(escaped),
+                None => break,
+            },
+            '"' if depth == 0 => return Ok(value),
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+                depth += 1;
+                value.push_str("${");
+            }
+            '{' if depth > 0 => {
+                depth += 1;
+                value.push(c);
+            }
+            '}' if depth > 0 => {
+                depth -= 1;
+                value.push(c);
+            }
+            '\n' if depth == 0 => break,
+            _ => value.push(c),
+        }
+    }
+
+    Err(String::from("unterminated string"))
+}
+
+// Reads the heredoc string (`<<EOF ... EOF` or `<<-EOF ... EOF`). The common
+// indentation of the lines is removed from the indented heredoc.
+fn read_heredoc(chars: &mut Scanner) -> Result<String, String> {
+    let indented = chars.peek() == Some(&'-');
+    if indented {
+        chars.next();
+    }
+
+    let mut marker = String::new();
+    for c in chars.by_ref() {
+        match c {
+            '\n' => break,
+            _ => marker.push(c),
+        }
+    }
+    let marker = marker.trim().to_string();
+
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut terminated = false;
+    for c in chars.by_ref() {
+        match c {
+            '\n' if line.trim() == marker => {
+                terminated = true;
+                break;
+            }
+            '\n' => lines.push(std::mem::take(&mut line)),
+            _ => line.push(c),
+        }
+    }
+    if !terminated && line.trim() != marker {
+        return Err(format!("unterminated heredoc {}", marker));
+    }
+
+    if indented {
+        let indentation = lines
+            .iter()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.len() - line.trim_start().len())
+            .min()
+            .unwrap_or(0);
+        lines = lines
+            .into_iter()
+            .map(|line| line.chars().skip(indentation).collect())
+            .collect();
+    }
+    Ok(lines.join("\n"))
+}
+
+struct Parser<'a> {
+    content: &'a str,
+    tokens: Vec<Token>,
+    spans: Vec<Range<usize>>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek() == Some(&Token::Newline) {
+            self.position += 1;
+        }
+    }
+
+    // Parses attributes and blocks until the end of file or the `terminator` symbol.
+    fn parse_body(&mut self, terminator: Option<char>) -> Result<Vec<HclItem>, String> {
+        let mut items = Vec::new();
+
+        loop {
+            self.skip_newlines();
+            match self.next() {
+                None => match terminator {
+                    Some(symbol) => return Err(format!("expected `{}`", symbol)),
+                    None => return Ok(items),
+                },
+                Some(Token::Symbol(symbol)) if Some(symbol) == terminator => return Ok(items),
+                Some(Token::Identifier(name)) | Some(Token::String(name)) => {
+                    items.push(self.parse_item(name)?)
+                }
+                Some(token) => return Err(format!("unexpected {:?}", token)),
+            }
+        }
+    }
+
+    fn parse_item(&mut self, name: String) -> Result<HclItem, String> {
+        let mut labels = Vec::new();
+
+        loop {
+            match self.next() {
+                Some(Token::Symbol('=')) | Some(Token::Symbol(':')) if labels.is_empty() => {
+                    let value = self.parse_expression()?;
+                    return Ok(HclItem::Attribute(name, value));
+                }
+                Some(Token::Symbol('{')) => {
+                    let body = self.parse_body(Some('}'))?;
+                    return Ok(HclItem::Block(name, labels, body));
+                }
+                Some(Token::String(label)) | Some(Token::Identifier(label)) => labels.push(label),
+                token => return Err(format!("unexpected {:?} after `{}`", token, name)),
+            }
+        }
+    }
+
+    // Parses the value of the attribute. Anything that isn't a literal is
+    // returned as the raw expression.
+    fn parse_expression(&mut self) -> Result<HclValue, String> {
+        let start = self.position;
+        let value = self.parse_primary()?;
+
+        match self.peek() {
+            None
+            | Some(Token::Newline)
+            | Some(Token::Symbol(','))
+            | Some(Token::Symbol('}'))
+            | Some(Token::Symbol(']'))
+            | Some(Token::Symbol(')')) => Ok(value),
+            _ => {
+                self.skip_expression()?;
+                Ok(HclValue::Expression(self.get_raw_text(start)))
+            }
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<HclValue, String> {
+        let start = self.position;
+        match self.next() {
+            Some(Token::String(value)) => Ok(HclValue::String(value)),
+            Some(Token::Number(value)) => Ok(HclValue::Number(value)),
+            Some(Token::Symbol('-')) if matches!(self.peek(), Some(Token::Number(_))) => {
+                match self.next() {
+                    Some(Token::Number(value)) => Ok(HclValue::Number(format!("-{}", value))),
+                    token => Err(format!("unexpected {:?} after `-`", token)),
+                }
+            }
+            Some(Token::Identifier(name)) => match name.as_str() {
+                "true" => Ok(HclValue::Bool(true)),
+                "false" => Ok(HclValue::Bool(false)),
+                "null" => Ok(HclValue::Null),
+                _ => {
+                    self.skip_expression()?;
+                    Ok(HclValue::Expression(self.get_raw_text(start)))
+                }
+            },
+            Some(Token::Symbol('[')) if self.is_for_expression() => {
+                self.skip_until(']')?;
+                self.skip_expression()?;
+                Ok(HclValue::Expression(self.get_raw_text(start)))
+            }
+            Some(Token::Symbol('[')) => {
+                let mut values = Vec::new();
+                loop {
+                    self.skip_newlines();
+                    if self.peek() == Some(&Token::Symbol(']')) {
+                        self.next();
+                        return Ok(HclValue::List(values));
+                    }
+                    values.push(self.parse_expression()?);
+                    self.skip_newlines();
+                    match self.next() {
+                        Some(Token::Symbol(',')) => {}
+                        Some(Token::Symbol(']')) => return Ok(HclValue::List(values)),
+                        token => return Err(format!("unexpected {:?} in list", token)),
+                    }
+                }
+            }
+            Some(Token::Symbol('{')) => match self.parse_map() {
+                Ok(values) => Ok(HclValue::Map(values)),
+                Err(_) => {
+                    // Not a literal map, e.g. a `for` expression or a map
+                    // with the computed keys.
+                    self.position = start + 1;
+                    self.skip_until('}')?;
+                    self.skip_expression()?;
+                    Ok(HclValue::Expression(self.get_raw_text(start)))
+                }
+            },
+            Some(Token::Symbol('(')) => {
+                self.skip_until(')')?;
+                self.skip_expression()?;
+                Ok(HclValue::Expression(self.get_raw_text(start)))
+            }
+            None => Err(String::from("unexpected end of file")),
+            Some(token @ Token::Newline) | Some(token @ Token::Symbol(',' | '}' | ']' | ')')) => {
+                Err(format!("unexpected {:?}", token))
+            }
+            // Operators and other symbols, e.g. `!var.enabled` or `-var.offset`.
+            Some(Token::Symbol(_)) => {
+                self.skip_expression()?;
+                Ok(HclValue::Expression(self.get_raw_text(start)))
+            }
+        }
+    }
+
+    // Checks that the next token after the newlines is the `for` keyword.
+    fn is_for_expression(&self) -> bool {
+        self.tokens[self.position.min(self.tokens.len())..]
+            .iter()
+            .find(|token| **token != Token::Newline)
+            == Some(&Token::Identifier(String::from("for")))
+    }
+
+    // Parses the items of the literal map after the opening `{` symbol.
+    fn parse_map(&mut self) -> Result<Vec<(String, HclValue)>, String> {
+        let mut values = Vec::new();
+        loop {
+            self.skip_newlines();
+            let key = match self.next() {
+                Some(Token::Symbol('}')) => return Ok(values),
+                Some(Token::Identifier(key)) | Some(Token::String(key)) => key,
+                token => return Err(format!("unexpected {:?} in map", token)),
+            };
+            match self.next() {
+                Some(Token::Symbol('=')) | Some(Token::Symbol(':')) => {}
+                token => return Err(format!("unexpected {:?} after `{}`", token, key)),
+            }
+            values.push((key, self.parse_expression()?));
+            if self.peek() == Some(&Token::Symbol(',')) {
+                self.next();
+            }
+        }
+    }
+
+    // Skips the rest of the expression until the end of line or the
+    // closing symbol of the enclosing list, map or call.
+    fn skip_expression(&mut self) -> Result<(), String> {
+        loop {
+            match self.peek() {
+                None
+                | Some(Token::Newline)
+                | Some(Token::Symbol(','))
+                | Some(Token::Symbol('}'))
+                | Some(Token::Symbol(']'))
+                | Some(Token::Symbol(')')) => return Ok(()),
+                Some(Token::Symbol('(')) => {
+                    self.next();
+                    self.skip_until(')')?;
+                }
+                Some(Token::Symbol('[')) => {
+                    self.next();
+                    self.skip_until(']')?;
+                }
+                Some(Token::Symbol('{')) => {
+                    self.next();
+                    self.skip_until('}')?;
+                }
+                Some(_) => {
+                    self.next();
+                }
+            }
+        }
+    }
+
+    // Skips tokens until the given closing symbol, including nested groups.
+    fn skip_until(&mut self, closing: char) -> Result<(), String> {
+        loop {
+            match self.next() {
+                None => return Err(format!("expected `{}`", closing)),
+                Some(Token::Symbol(symbol)) if symbol == closing => return Ok(()),
+                Some(Token::Symbol('(')) => self.skip_until(')')?,
+                Some(Token::Symbol('[')) => self.skip_until(']')?,
+                Some(Token::Symbol('{')) => self.skip_until('}')?,
+                Some(_) => {}
+            }
+        }
+    }
+
+    // Returns the source text of the tokens starting from `start` position
+    // until the current one.
+    fn get_raw_text(&self, start: usize) -> String {
+        let end = self.position.min(self.spans.len());
+        match start < end {
+            true => self.content[self.spans[start].start..self.spans[end - 1].end].to_string(),
+            false => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hcl::{parse_hcl, HclItem, HclValue};
+
+    #[test]
+    fn test_parse_hcl_returns_blocks_and_attributes() {
+        let filename = String::from("variables.tf");
+        let content = r#"
+            # Comment
+            variable "environment" {
+              type = "string"
+              default = "unknown"
+            }
+
+            variable "subnets" {
+              type        = list(object({ cidr = string }))
+              description = <<EOF
+            Subnets for ${var.environment}
+            EOF
+            }
+
+            /* multiline
+               comment */
+            count = 3 // another comment
+        "#;
+        let items = parse_hcl(&filename, content).unwrap();
+
+        assert_eq!(items.len(), 3);
+        assert_eq!(
+            items[0],
+            HclItem::Block(
+                "variable".to_string(),
+                vec!["environment".to_string()],
+                vec![
+                    HclItem::Attribute("type".to_string(), HclValue::String("string".to_string())),
+                    HclItem::Attribute(
+                        "default".to_string(),
+                        HclValue::String("unknown".to_string())
+                    ),
+                ]
+            )
+        );
+        match &items[1] {
+            HclItem::Block(_, _, body) => assert_eq!(
+                body[0],
+                HclItem::Attribute(
+                    "type".to_string(),
+                    HclValue::Expression("list(object({ cidr = string }))".to_string())
+                )
+            ),
+            _ => panic!("expected the variable block"),
+        }
+        assert_eq!(
+            items[2],
+            HclItem::Attribute("count".to_string(), HclValue::Number("3".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_hcl_returns_literal_values() {
+        let filename = String::from("variables.tfvars");
+        let content = r#"
+            name    = "app-${var.environment}"
+            enabled = true
+            ports   = [80, 443]
+            tags    = {
+              Name = "app"
+              "Team" : "devops",
+            }
+            region  = var.region
+        "#;
+        let items = parse_hcl(&filename, content).unwrap();
+
+        let values: Vec<HclValue> = items
+            .into_iter()
+            .map(|item| match item {
+                HclItem::Attribute(_, value) => value,
+                HclItem::Block(..) => panic!("expected the attribute"),
+            })
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                HclValue::String("app-${var.environment}".to_string()),
+                HclValue::Bool(true),
+                HclValue::List(vec![
+                    HclValue::Number("80".to_string()),
+                    HclValue::Number("443".to_string())
+                ]),
+                HclValue::Map(vec![
+                    ("Name".to_string(), HclValue::String("app".to_string())),
+                    ("Team".to_string(), HclValue::String("devops".to_string())),
+                ]),
+                HclValue::Expression("var.region".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_hcl_returns_expressions_for_operators_and_for_expressions() {
+        let filename = String::from("main.tf");
+        let content = r#"
+            disabled = !var.enabled
+            offset   = -var.offset
+            names    = {for k, v in var.m : k => v.name}
+            ids      = [for v in var.l : v.id]
+            tags     = {(local.key) = "value"}
+            count    = -1
+            half     = 10/2 # division
+        "#;
+        let items = parse_hcl(&filename, content).unwrap();
+
+        let values: Vec<HclValue> = items
+            .into_iter()
+            .map(|item| match item {
+                HclItem::Attribute(_, value) => value,
+                HclItem::Block(..) => panic!("expected the attribute"),
+            })
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                HclValue::Expression("!var.enabled".to_string()),
+                HclValue::Expression("-var.offset".to_string()),
+                HclValue::Expression("{for k, v in var.m : k => v.name}".to_string()),
+                HclValue::Expression("[for v in var.l : v.id]".to_string()),
+                HclValue::Expression("{(local.key) = \"value\"}".to_string()),
+                HclValue::Number("-1".to_string()),
+                HclValue::Expression("10/2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_hcl_removes_indentation_of_indented_heredoc() {
+        let filename = String::from("main.tf");
+        let content = "policy = <<-EOT\n    {\n      \"Version\": \"1\"\n    }\n    EOT\n";
+        let items = parse_hcl(&filename, content).unwrap();
+
+        assert_eq!(
+            items,
+            vec![HclItem::Attribute(
+                "policy".to_string(),
+                HclValue::String("{\n  \"Version\": \"1\"\n}".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_parse_hcl_returns_error_for_unterminated_string() {
+        let filename = String::from("variables.tfvars");
+        let result = parse_hcl(&filename, "name = \"app\n");
+
+        assert_eq!(result.is_err(), true);
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::process::{Command, Stdio};
+
+use crate::error::SageError;
+use crate::terminal::print_info;
+
+pub const HOOK_NAMES: &'static [&str; 9] = &[
+    "before_init",
+    "after_init",
+    "before_plan",
+    "after_plan",
+    "before_apply",
+    "after_apply",
+    "before_destroy",
+    "after_destroy",
+    "on_failure",
+];
+pub const BEFORE_HOOK_PREFIX: &'static str = "before_";
+pub const AFTER_HOOK_PREFIX: &'static str = "after_";
+pub const ON_FAILURE_HOOK: &'static str = "on_failure";
+
+// Shell commands of the hooks by the hook name.
+pub type Hooks = BTreeMap<String, Vec<String>>;
+
+// Information about the executed command, passed to the hooks via the
+// environment variables.
+#[derive(Debug, Clone, Default)]
+pub struct HookContext {
+    pub command: String,
+    pub config: String,
+    pub directory: String,
+    pub stack: Option<String>,
+    pub exit_code: i32,
+    pub error: Option<String>,
+}
+
+impl HookContext {
+    // Returns the environment variables for the hooks. The exit status is
+    // passed only to the hooks, which run after the command. It is the exit
+    // code of the failed Terraform command, or 1 for the other failures.
+    fn get_variables(&self, hook: &str) -> Vec<(&'static str, String)> {
+        let mut variables = vec![
+            ("SAGE_HOOK", hook.to_string()),
+            ("SAGE_COMMAND", self.command.clone()),
+            ("SAGE_CONFIG", self.config.clone()),
+            ("SAGE_DIRECTORY", self.directory.clone()),
+        ];
+        if let Some(stack) = &self.stack {
+            variables.push(("SAGE_STACK", stack.clone()));
+        }
+        if !hook.starts_with(BEFORE_HOOK_PREFIX) {
+            variables.push(("SAGE_EXIT_STATUS", self.exit_code.to_string()));
+        }
+        if let Some(error) = &self.error {
+            variables.push(("SAGE_ERROR", error.clone()));
+        }
+        variables
+    }
+}
+
+// Runs the shell commands of the hook with `hook` name one by one in the
+// directory of the context. Stops on the first failed command.
+pub fn run_hooks(
+    commands: &Vec<String>,
+    hook: &str,
+    context: &HookContext,
+) -> Result<(), SageError> {
+    for command in commands.iter() {
+        print_info(&format!("Running {} hook: `{}`", hook, command));
+        let mut process = match cfg!(windows) {
+            true => {
+                let mut process = Command::new("cmd");
+                process.arg("/C").arg(command);
+                process
+            }
+            false => {
+                let mut process = Command::new("sh");
+                process.arg("-c").arg(command);
+                process
+            }
+        };
+        let status = process
+            .current_dir(&context.directory)
+            .envs(context.get_variables(hook))
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .map_err(|err| {
+                SageError::HookFailed(hook.to_string(), command.clone(), err.to_string())
+            })?;
+
+        if !status.success() {
+            let message = match status.code() {
+                Some(code) => format!("exited with code {}", code),
+                None => String::from("terminated by signal"),
+            };
+            return Err(SageError::HookFailed(
+                hook.to_string(),
+                command.clone(),
+                message,
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::hooks::{run_hooks, HookContext};
+    use crate::utils::create_test_directory;
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_hooks() {
+        let directory = create_test_directory("hooks")
+            .to_string_lossy()
+            .into_owned();
+        let context = HookContext {
+            command: String::from("apply"),
+            config: String::from("production"),
+            directory: directory.clone(),
+            ..HookContext::default()
+        };
+
+        let commands = vec![
+            String::from(
+                "echo \"$SAGE_HOOK $SAGE_COMMAND $SAGE_CONFIG $SAGE_EXIT_STATUS\" > hook.txt",
+            ),
+            String::from("exit 3"),
+            String::from("echo unreachable > unreachable.txt"),
+        ];
+        let result = run_hooks(&commands, "after_apply", &context);
+        let failure_context = HookContext {
+            exit_code: 2,
+            error: Some(String::from("Terraform `apply` command failed")),
+            ..context.clone()
+        };
+        let failure_commands = vec![String::from("echo \"$SAGE_EXIT_STATUS\" > failure.txt")];
+        run_hooks(&failure_commands, "on_failure", &failure_context).unwrap();
+        let output = fs::read_to_string(format!("{}/hook.txt", directory)).unwrap();
+        let failure_output = fs::read_to_string(format!("{}/failure.txt", directory)).unwrap();
+        let unreachable = fs::metadata(format!("{}/unreachable.txt", directory)).is_ok();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(output, "after_apply apply production 0\n");
+        assert_eq!(failure_output, "2\n");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The after_apply hook `exit 3` failed: exited with code 3"
+        );
+        assert_eq!(unreachable, false);
+    }
+}
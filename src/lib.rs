@@ -0,0 +1,37 @@
+// The lints below are against the code style used across the project (e.g.
+// `&String` parameters, `assert_eq!` with booleans in tests, the explicit
+// `'static` lifetimes of constants and the client methods taking all command
+// options), so they are disabled instead of rewriting the existing code.
+#![allow(
+    clippy::bool_assert_comparison,
+    clippy::ptr_arg,
+    clippy::redundant_static_lifetimes,
+    clippy::result_large_err,
+    clippy::too_many_arguments
+)]
+
+pub mod audit;
+pub mod cli;
+pub mod client;
+pub mod drift;
+pub mod error;
+pub mod git;
+pub mod hcl;
+pub mod hooks;
+pub mod lint;
+pub mod manifest;
+pub mod metadata;
+pub mod outputs;
+pub mod plan;
+pub mod policy;
+pub mod project;
+pub mod report;
+pub mod scaffold;
+pub mod template;
+pub mod terminal;
+pub mod terraform;
+pub mod utils;
+pub mod vars;
+
+pub use crate::error::SageError;
+pub use crate::template::render;
@@ -0,0 +1,377 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use handlebars::template::{HelperTemplate, Parameter, Template, TemplateElement};
+use handlebars::Path as TemplatePath;
+use quick_error::ResultExt;
+use toml::Value as TomlValue;
+
+use crate::error::SageError;
+use crate::metadata::METADATA_SECTION_NAME;
+use crate::report::{ReportCase, ReportOutcome};
+use crate::template::{
+    format_section_name, get_interpolated_keys, get_raw_template_context, get_template_context,
+    read_context_file, BUILTIN_CONTEXT_SOURCE, DEFAULTS_SECTION_NAME, SECRETS_PARAM,
+};
+use crate::utils::{get_configs, CONFIG_NAME_SEPARATOR};
+
+// Helpers that change the scope of the nested block, so the placeholders
+// inside of it are not the keys of the template context.
+pub const SCOPED_HELPERS: &'static [&str; 2] = &["each", "with"];
+pub const LINT_REPORT_NAME: &'static str = "lint";
+pub const CONTEXT_SECTIONS_CASE_NAME: &'static str = "context sections";
+
+// Problems found in the template and the context file.
+#[derive(Debug, Default)]
+pub struct LintReport {
+    // Names of the checked configurations.
+    pub configs: Vec<String>,
+    // Placeholders used in the template, but not defined for the configuration.
+    pub missing_keys: BTreeMap<String, Vec<String>>,
+    // Keys defined in the context section, but never used in the template.
+    pub unused_keys: BTreeMap<String, Vec<String>>,
+    // Keys of the `[defaults]` section, used by none of the configurations.
+    pub unused_defaults: Vec<String>,
+    // Sections in the context file without the matching configuration directory.
+    pub unknown_sections: Vec<String>,
+}
+
+impl LintReport {
+    // Returns the total number of found problems.
+    pub fn problems_count(&self) -> usize {
+        let missing: usize = self.missing_keys.values().map(|keys| keys.len()).sum();
+        let unused: usize = self.unused_keys.values().map(|keys| keys.len()).sum();
+        missing + unused + self.unused_defaults.len() + self.unknown_sections.len()
+    }
+
+    // Returns the problems as the report cases: one case per configuration
+    // and one case for the sections of the context file.
+    pub fn get_report_cases(&self) -> Vec<ReportCase> {
+        let get_outcome = |count: usize| match count {
+            0 => ReportOutcome::Passed,
+            count => ReportOutcome::Failed(format!("{} problem(s)", count)),
+        };
+
+        let mut cases: Vec<ReportCase> = self
+            .configs
+            .iter()
+            .map(|config| {
+                let mut details: Vec<String> = Vec::new();
+                if let Some(keys) = self.missing_keys.get(config) {
+                    keys.iter().for_each(|key| {
+                        details.push(format!("Placeholder {} is not defined", key))
+                    });
+                }
+                if let Some(keys) = self.unused_keys.get(config) {
+                    keys.iter()
+                        .for_each(|key| details.push(format!("Key {} is never used", key)));
+                }
+                ReportCase {
+                    name: config.clone(),
+                    group: LINT_REPORT_NAME.to_string(),
+                    outcome: get_outcome(details.len()),
+                    details,
+                }
+            })
+            .collect();
+
+        let details: Vec<String> = self
+            .unused_defaults
+            .iter()
+            .map(|key| {
+                format!(
+                    "Key {} of the [{}] section is never used",
+                    key, DEFAULTS_SECTION_NAME
+                )
+            })
+            .chain(
+                self.unknown_sections
+                    .iter()
+                    .map(|section| format!("Section [{}] has no configuration directory", section)),
+            )
+            .collect();
+        cases.push(ReportCase {
+            name: CONTEXT_SECTIONS_CASE_NAME.to_string(),
+            group: LINT_REPORT_NAME.to_string(),
+            outcome: get_outcome(details.len()),
+            details,
+        });
+        cases
+    }
+}
+
+// Checks the template with `template` name, located in the directory specified
+// in `directory` parameter, against the context of each available configuration.
+// Keys are used, when the template or the other values of the context refer to
+// them. Keys of the `[defaults]` section are reported once for all
+// configurations.
+pub fn lint_project(directory: &String, template: &String) -> Result<LintReport, SageError> {
+    let mut report = LintReport::default();
+    let path_to_template = Path::new(directory)
+        .join(template)
+        .to_string_lossy()
+        .into_owned();
+    let source = fs::read_to_string(&path_to_template).context(&path_to_template)?;
+    let placeholders = get_template_placeholders(&path_to_template, &source)?;
+
+    let configs = get_configs(directory)?;
+    let mut config_names: Vec<&String> = configs.keys().collect();
+    config_names.sort();
+    let context_file = read_context_file(directory)?;
+    let defaults_source = context_file
+        .as_ref()
+        .map(|(toml_path, _toml_root)| format!("{} [{}]", toml_path, DEFAULTS_SECTION_NAME));
+
+    let mut used_keys = placeholders.clone();
+    for config in config_names {
+        report.configs.push(config.clone());
+        let context = get_template_context(directory, config)?;
+        let referenced_keys: BTreeSet<String> = get_raw_template_context(directory, config)?
+            .values()
+            .flat_map(|entry| get_interpolated_keys(&entry.value))
+            .collect();
+        used_keys.extend(referenced_keys.iter().cloned());
+
+        let missing_keys: Vec<String> = placeholders
+            .iter()
+            .filter(|key| !context.contains_key(*key))
+            .cloned()
+            .collect();
+        if !missing_keys.is_empty() {
+            report.missing_keys.insert(config.clone(), missing_keys);
+        }
+
+        let unused_keys: Vec<String> = context
+            .iter()
+            .filter(|(_key, entry)| entry.source != BUILTIN_CONTEXT_SOURCE)
+            .filter(|(_key, entry)| {
+                !defaults_source
+                    .as_ref()
+                    .is_some_and(|source| entry.source.starts_with(source))
+            })
+            .filter(|(key, _entry)| !placeholders.contains(*key))
+            .filter(|(key, _entry)| !referenced_keys.contains(*key))
+            .map(|(key, _entry)| key.clone())
+            .collect();
+        if !unused_keys.is_empty() {
+            report.unused_keys.insert(config.clone(), unused_keys);
+        }
+    }
+
+    if let Some((_toml_path, toml_root)) = context_file {
+        if let Some(defaults) = toml_root
+            .get(DEFAULTS_SECTION_NAME)
+            .and_then(|s| s.as_table())
+        {
+            report.unused_defaults = defaults
+                .keys()
+                .filter(|key| key.as_str() != SECRETS_PARAM && !used_keys.contains(*key))
+                .cloned()
+                .collect();
+        }
+
+        let config_names: Vec<&String> = configs.keys().collect();
+        collect_unknown_sections(
+            &toml_root,
+            None,
+            &config_names,
+            &mut report.unknown_sections,
+        );
+    }
+
+    Ok(report)
+}
+
+// Collects the sections of the context file without the matching configuration
+// directory. Sections of the parent directories of the nested configurations
+// are checked recursively.
+fn collect_unknown_sections(
+    table: &TomlValue,
+    prefix: Option<&String>,
+    configs: &Vec<&String>,
+    unknown_sections: &mut Vec<String>,
+) {
+    let sections = match table.as_table() {
+        Some(sections) => sections,
+        None => return,
+    };
+
+    sections
+        .iter()
+        .filter(|(_name, value)| value.is_table())
+        .filter(|(name, _value)| match prefix {
+            Some(_) => name.as_str() != METADATA_SECTION_NAME,
+            None => name.as_str() != DEFAULTS_SECTION_NAME,
+        })
+        .for_each(|(name, value)| {
+            let config = match prefix {
+                Some(prefix) => format!("{}{}{}", prefix, CONFIG_NAME_SEPARATOR, name),
+                None => name.clone(),
+            };
+            let parent_prefix = format!("{}{}", config, CONFIG_NAME_SEPARATOR);
+
+            if configs.contains(&&config) {
+                return;
+            }
+            match configs.iter().any(|name| name.starts_with(&parent_prefix)) {
+                true => collect_unknown_sections(value, Some(&config), configs, unknown_sections),
+                false => unknown_sections.push(format_section_name(&config)),
+            }
+        });
+}
+
+// Returns the names of the context keys referenced in the template source.
+pub fn get_template_placeholders(
+    filename: &String,
+    source: &str,
+) -> Result<BTreeSet<String>, SageError> {
+    let template = Template::compile(source).context(filename)?;
+    let mut placeholders = BTreeSet::new();
+    collect_template_placeholders(&template, &mut placeholders);
+    Ok(placeholders)
+}
+
+// Walks through the elements of the template and collects the placeholders.
+fn collect_template_placeholders(template: &Template, placeholders: &mut BTreeSet<String>) {
+    template.elements.iter().for_each(|element| match element {
+        TemplateElement::HTMLExpression(parameter) => {
+            collect_parameter_placeholders(parameter, placeholders)
+        }
+        TemplateElement::Expression(helper) | TemplateElement::HelperBlock(helper) => {
+            collect_helper_placeholders(helper, placeholders)
+        }
+        _ => {}
+    });
+}
+
+// Collects placeholders used in the expression or the helper block.
+fn collect_helper_placeholders(helper: &HelperTemplate, placeholders: &mut BTreeSet<String>) {
+    let is_name_only = !helper.block && helper.params.is_empty() && helper.hash.is_empty();
+    match &helper.name {
+        Parameter::Name(name) if is_name_only => {
+            add_placeholder(name, placeholders);
+        }
+        parameter => collect_parameter_placeholders(parameter, placeholders),
+    }
+
+    helper
+        .params
+        .iter()
+        .chain(helper.hash.values())
+        .for_each(|parameter| collect_parameter_placeholders(parameter, placeholders));
+
+    let is_scoped = match &helper.name {
+        Parameter::Name(name) => SCOPED_HELPERS.contains(&name.as_str()),
+        _ => false,
+    };
+    if let Some(template) = &helper.template {
+        if !is_scoped {
+            collect_template_placeholders(template, placeholders);
+        }
+    }
+    if let Some(template) = &helper.inverse {
+        collect_template_placeholders(template, placeholders);
+    }
+}
+
+// Collects placeholders used in the helper's parameter.
+fn collect_parameter_placeholders(parameter: &Parameter, placeholders: &mut BTreeSet<String>) {
+    match parameter {
+        Parameter::Path(TemplatePath::Relative((_segments, raw))) => {
+            add_placeholder(raw, placeholders)
+        }
+        Parameter::Subexpression(subexpression) => match subexpression.element.as_ref() {
+            TemplateElement::Expression(helper) => {
+                collect_helper_placeholders(helper, placeholders)
+            }
+            TemplateElement::HTMLExpression(parameter) => {
+                collect_parameter_placeholders(parameter, placeholders)
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+// Adds the top-level key of the path to the placeholders. Paths relative
+// to the current or parent scope are ignored.
+fn add_placeholder(raw_path: &String, placeholders: &mut BTreeSet<String>) {
+    let key = raw_path.split(['.', '/']).next().unwrap_or_default();
+
+    if !key.is_empty() && key != "this" && !key.starts_with('@') {
+        placeholders.insert(key.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::lint::{get_template_placeholders, lint_project};
+    use crate::report::ReportOutcome;
+    use crate::utils::create_test_directory;
+
+    #[test]
+    fn test_get_template_placeholders() {
+        let filename = String::from("main.tpl");
+        let source = r#"
+            key = "state-{{CONFIG_NAME}}"
+            {{#if enable_logs}}bucket = "{{logs_bucket}}"{{else}}{{fallback}}{{/if}}
+            {{#each subnets}}"{{this}}" {{name}}{{/each}}
+            region = "{{lookup regions CONFIG_NAME}}"
+            zone = "{{aws.zone}}"
+        "#;
+        let placeholders = get_template_placeholders(&filename, source).unwrap();
+
+        let expected = vec![
+            "CONFIG_NAME",
+            "aws",
+            "enable_logs",
+            "fallback",
+            "logs_bucket",
+            "regions",
+            "subnets",
+        ];
+        assert_eq!(placeholders.into_iter().collect::<Vec<String>>(), expected);
+    }
+
+    #[test]
+    fn test_lint_project_checks_interpolated_and_default_keys() {
+        let path = create_test_directory("lint-keys");
+        let directory = path.to_string_lossy().into_owned();
+        fs::create_dir_all(path.join("configs/dev")).unwrap();
+        fs::create_dir_all(path.join("configs/production")).unwrap();
+        fs::write(path.join("main.tpl"), "bucket = \"{{bucket}}\"\n").unwrap();
+        let context = "[defaults]\nregion = \"us-east-1\"\nowner = \"devops\"\n\n\
+                       [dev]\nbucket = \"{{region}}-data\"\n\n\
+                       [production]\nbucket = \"data\"\nzone = \"a\"\n";
+        fs::write(path.join("configs/context.toml"), context).unwrap();
+
+        let template = String::from("main.tpl");
+        let report = lint_project(&directory, &template).unwrap();
+        fs::remove_dir_all(&path).unwrap();
+
+        assert_eq!(report.unused_keys.contains_key("dev"), false);
+        assert_eq!(report.unused_keys["production"], vec!["zone"]);
+        assert_eq!(report.unused_defaults, vec!["owner"]);
+        assert_eq!(report.problems_count(), 2);
+    }
+
+    #[test]
+    fn test_lint_project_returns_empty_report() {
+        let directory = String::from("./examples/context_switch");
+        let template = String::from("main.tpl");
+        let report = lint_project(&directory, &template).unwrap();
+
+        assert_eq!(report.problems_count(), 0);
+        let cases = report.get_report_cases();
+        assert_eq!(cases.len(), report.configs.len() + 1);
+        assert_eq!(
+            cases
+                .iter()
+                .all(|case| case.outcome == ReportOutcome::Passed),
+            true
+        );
+    }
+}
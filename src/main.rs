@@ -1,18 +1,13 @@
-mod cli;
-mod client;
-mod error;
-mod template;
-mod terminal;
-mod terraform;
-mod utils;
+use std::process;
 
 use structopt::StructOpt;
 
-use crate::cli::Command;
-use crate::client::SageClient;
+use terraform_sage::cli::Command;
+use terraform_sage::client::SageClient;
 
 fn main() {
     let command = Command::from_args();
     let client = SageClient::new();
-    client.run(&command);
+    let exit_code = client.run(&command);
+    process::exit(exit_code);
 }
@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use quick_error::ResultExt;
+use serde::{Deserialize, Serialize};
+
+use crate::error::SageError;
+use crate::terminal::print_warning;
+use crate::utils::write_file_atomically;
+
+pub const SAGE_DIRECTORY_NAME: &'static str = ".sage";
+pub const MANIFEST_FILE_NAME: &'static str = "manifest.json";
+
+// Describes the file generated by terraform-sage.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ManifestEntry {
+    pub template: String,
+    pub config: String,
+    pub hash: String,
+    pub template_hash: String,
+}
+
+// List of the files generated by terraform-sage in the certain directory.
+// The manifest is stored in the `.sage/manifest.json` file of the directory,
+// where the keys are the paths of the files relative to it, i.e. the file
+// names of the files located next to the `.sage` directory.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Manifest {
+    #[serde(skip)]
+    path: PathBuf,
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+impl Manifest {
+    // Loads the manifest that tracks the file by path in `filepath` parameter.
+    // If the manifest doesn't exist or can't be read, then returns an empty one.
+    pub fn load_for(filepath: &String) -> Manifest {
+        Manifest::load(&get_directory(filepath))
+    }
+
+    // Loads the manifest of the files generated in the given directory.
+    pub fn load(directory: &Path) -> Manifest {
+        let path = directory.join(SAGE_DIRECTORY_NAME).join(MANIFEST_FILE_NAME);
+
+        let mut manifest = match fs::read_to_string(&path) {
+            Ok(raw_data) => serde_json::from_str(&raw_data).unwrap_or_else(|_| {
+                print_warning(&format!(
+                    "The {} manifest is malformed and will be recreated.",
+                    path.to_string_lossy()
+                ));
+                Manifest::default()
+            }),
+            Err(_) => Manifest::default(),
+        };
+        manifest.path = path;
+        manifest
+    }
+
+    // Returns information about the generated file by path in `filepath` parameter.
+    pub fn get(&self, filepath: &String) -> Option<&ManifestEntry> {
+        self.files.get(&self.get_key(filepath))
+    }
+
+    // Adds or replaces information about the generated file.
+    pub fn insert(&mut self, filepath: &String, entry: ManifestEntry) {
+        let key = self.get_key(filepath);
+        self.files.insert(key, entry);
+    }
+
+    // Removes information about the generated file.
+    pub fn remove(&mut self, filepath: &String) -> Option<ManifestEntry> {
+        let key = self.get_key(filepath);
+        self.files.remove(&key)
+    }
+
+    // Saves the manifest on the disk.
+    pub fn save(&self) -> Result<(), SageError> {
+        let path = self.path.to_string_lossy().into_owned();
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory).context(&path)?;
+        }
+
+        let content = serde_json::to_string_pretty(&self).unwrap_or_default();
+        write_file_atomically(&path, &content)?;
+        Ok(())
+    }
+
+    // Returns the key of the file by path in `filepath` parameter: the path
+    // relative to the directory of the manifest, or the absolute path for the
+    // files outside of it.
+    fn get_key(&self, filepath: &String) -> String {
+        let canonicalize = |path: &Path| fs::canonicalize(path).unwrap_or(path.to_path_buf());
+        let base_directory = self
+            .path
+            .parent()
+            .and_then(|sage_directory| sage_directory.parent())
+            .map(canonicalize)
+            .unwrap_or_default();
+        let path = canonicalize(&get_directory(filepath)).join(get_file_name(filepath));
+
+        match path.strip_prefix(&base_directory) {
+            Ok(relative_path) => relative_path.to_string_lossy().into_owned(),
+            Err(_) => path.to_string_lossy().into_owned(),
+        }
+    }
+}
+
+// Returns the directory where the file by the given path is located.
+fn get_directory(filepath: &String) -> PathBuf {
+    match Path::new(filepath).parent() {
+        Some(directory) if directory != Path::new("") => directory.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+// Returns the name of the file by the given path.
+fn get_file_name(filepath: &String) -> String {
+    Path::new(filepath)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| filepath.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::manifest::{Manifest, ManifestEntry};
+    use crate::utils::create_test_directory;
+
+    #[test]
+    fn test_manifest_keys_are_relative_to_directory() {
+        let directory = create_test_directory("manifest");
+        let build_directory = directory.join("build").join("dev");
+        fs::create_dir_all(&build_directory).unwrap();
+        let entry = ManifestEntry {
+            template: String::from("template.tf"),
+            config: String::from("dev"),
+            hash: String::new(),
+            template_hash: String::new(),
+        };
+        let path_to_main = directory.join("main.tf").to_string_lossy().into_owned();
+        let path_to_build = build_directory
+            .join("main.tf")
+            .to_string_lossy()
+            .into_owned();
+
+        let mut manifest = Manifest::load(&directory);
+        manifest.insert(&path_to_main, entry.clone());
+        manifest.insert(&path_to_build, entry.clone());
+        manifest.save().unwrap();
+        let manifest = Manifest::load(&directory);
+        let is_main_tracked = Manifest::load_for(&path_to_main)
+            .get(&path_to_main)
+            .is_some();
+        let keys: Vec<&String> = manifest.files.keys().collect();
+        let has_build_manifest = build_directory.join(".sage").exists();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(keys, vec!["build/dev/main.tf", "main.tf"]);
+        assert_eq!(manifest.get(&path_to_build), Some(&entry));
+        assert_eq!(is_main_tracked, true);
+        assert_eq!(has_build_manifest, false);
+    }
+}
@@ -0,0 +1,126 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+use toml::Value as TomlValue;
+
+use crate::error::SageError;
+use crate::template::{
+    format_section_name, get_context_section, parse_context_file, read_context_file,
+};
+
+pub const METADATA_SECTION_NAME: &'static str = "meta";
+pub const METADATA_FILE_NAME: &'static str = "meta.toml";
+
+// Descriptive information about the configuration. Deleting resources in
+// protected configurations requires an explicit confirmation, and resources
+// of the types in `prevent_destroy_types` can't be deleted at all.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct ConfigMetadata {
+    pub description: String,
+    pub owner: String,
+    pub protected: bool,
+    pub tags: Vec<String>,
+    pub prevent_destroy_types: Vec<String>,
+}
+
+// Returns metadata of the configuration with `config` name. The metadata is
+// taken from the `[<config>.meta]` section of the context file, or from the
+// `meta.toml` file in the configuration directory specified in `config_directory`
+// parameter. If neither of them exists, then returns empty metadata.
+pub fn get_config_metadata(
+    directory: &String,
+    config: &String,
+    config_directory: &String,
+) -> Result<ConfigMetadata, SageError> {
+    if let Some((toml_path, toml_root)) = read_context_file(directory)? {
+        let section = get_context_section(&toml_root, config)
+            .and_then(|section| section.get(METADATA_SECTION_NAME));
+        if let Some(value) = section {
+            let section_name = format_section_name(config);
+            let source = format!("{} [{}.{}]", toml_path, section_name, METADATA_SECTION_NAME);
+            return convert_toml_to_metadata(value, &source);
+        }
+    }
+
+    let metadata_path = Path::new(config_directory)
+        .join(METADATA_FILE_NAME)
+        .to_string_lossy()
+        .into_owned();
+    match fs::read_to_string(&metadata_path) {
+        Ok(raw_data) => {
+            let value = parse_context_file(&metadata_path, &raw_data)?;
+            convert_toml_to_metadata(&value, &metadata_path)
+        }
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(ConfigMetadata::default()),
+        Err(err) => Err(SageError::Io(err, metadata_path)),
+    }
+}
+
+// Converts the given sub-toml into the configuration metadata.
+fn convert_toml_to_metadata(
+    value: &TomlValue,
+    source: &String,
+) -> Result<ConfigMetadata, SageError> {
+    value.clone().try_into::<ConfigMetadata>().map_err(|err| {
+        let message = format!("The metadata in {} is invalid: {}.", source, err);
+        SageError::InvalidConfig(message)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::metadata::get_config_metadata;
+    use crate::utils::create_test_directory;
+
+    #[test]
+    fn test_get_config_metadata() {
+        let directory = create_test_directory("metadata")
+            .to_string_lossy()
+            .into_owned();
+        fs::create_dir_all(format!("{}/configs/dev", directory)).unwrap();
+        fs::create_dir_all(format!("{}/configs/production", directory)).unwrap();
+        let context = r#"
+            [production]
+            region = "us-east-1"
+
+            [production.meta]
+            description = "Customer-facing environment"
+            protected = true
+            tags = ["customer", "us"]
+            prevent_destroy_types = ["aws_db_instance"]
+        "#;
+        fs::write(format!("{}/configs/context.toml", directory), context).unwrap();
+        let metadata_file = "owner = \"platform-team\"\n";
+        fs::write(
+            format!("{}/configs/dev/meta.toml", directory),
+            metadata_file,
+        )
+        .unwrap();
+
+        let production = get_config_metadata(
+            &directory,
+            &"production".to_string(),
+            &format!("{}/configs/production", directory),
+        )
+        .unwrap();
+        let dev = get_config_metadata(
+            &directory,
+            &"dev".to_string(),
+            &format!("{}/configs/dev", directory),
+        )
+        .unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(production.description, "Customer-facing environment");
+        assert_eq!(production.protected, true);
+        assert_eq!(production.tags, vec!["customer", "us"]);
+        assert_eq!(production.prevent_destroy_types, vec!["aws_db_instance"]);
+        assert_eq!(dev.owner, "platform-team");
+        assert_eq!(dev.protected, false);
+    }
+}
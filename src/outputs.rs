@@ -0,0 +1,376 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use quick_error::ResultExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map as JsonMap, Value as JsonValue};
+
+use crate::error::SageError;
+use crate::manifest::SAGE_DIRECTORY_NAME;
+use crate::template::{TemplateContext, SECRET_VALUE_MASK};
+use crate::terraform::{CommandContext, TerraformClient};
+use crate::utils::write_file_atomically;
+
+pub const OUTPUTS_CACHE_FILE_NAME: &'static str = "outputs.json";
+
+// A single output of Terraform module, as printed by `terraform output -json`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TerraformOutput {
+    pub value: JsonValue,
+    #[serde(default)]
+    pub sensitive: bool,
+}
+
+// Outputs of Terraform module, ordered by the output names.
+pub type TerraformOutputs = BTreeMap<String, TerraformOutput>;
+
+// Outputs of the stack, saved in the `.sage/outputs.json` file of the stack
+// directory. The key is the configuration name.
+type OutputsCache = BTreeMap<String, TerraformOutputs>;
+
+// Returns the outputs of the configuration with `config` name for the stack,
+// located in the directory specified in `directory` parameter. The `extra`
+// arguments are passed to Terraform's output command as is.
+pub fn get_outputs(
+    directory: &String,
+    config: &String,
+    extra: &Vec<String>,
+) -> Result<TerraformOutputs, SageError> {
+    let terraform = TerraformClient::new();
+    let mut args = vec![String::from("-json")];
+    args.extend(extra.iter().cloned());
+    let context = CommandContext::new(directory, config, None)?;
+    let raw_data = terraform.call_with_output("output", &args, &context)?;
+
+    serde_json::from_str(&raw_data).map_err(|err| {
+        let message = format!("Unable to parse Terraform outputs: {}.", err);
+        SageError::InvalidConfig(message)
+    })
+}
+
+// Returns the outputs of the stack like `get_outputs`, but reads them from the
+// cache of the stack, when they were read before. Otherwise the outputs are
+// read from Terraform and saved in the cache.
+fn get_cached_outputs(directory: &String, config: &String) -> Result<TerraformOutputs, SageError> {
+    if let Some(outputs) = load_outputs_cache(directory).remove(config) {
+        return Ok(outputs);
+    }
+    let outputs = get_outputs(directory, config, &vec![])?;
+    update_outputs_cache(directory, config, Some(outputs.clone()))?;
+    Ok(outputs)
+}
+
+// Saves the outputs of the configuration with `config` name in the cache of
+// the stack, located in the directory specified in `directory` parameter.
+pub fn save_cached_outputs(
+    directory: &String,
+    config: &String,
+    outputs: &TerraformOutputs,
+) -> Result<(), SageError> {
+    update_outputs_cache(directory, config, Some(outputs.clone()))
+}
+
+// Removes the outputs of the configuration with `config` name from the cache
+// of the stack, so that they are read from Terraform next time.
+pub fn forget_cached_outputs(directory: &String, config: &String) -> Result<(), SageError> {
+    match load_outputs_cache(directory).contains_key(config) {
+        true => update_outputs_cache(directory, config, None),
+        false => Ok(()),
+    }
+}
+
+fn get_outputs_cache_path(directory: &String) -> String {
+    Path::new(directory)
+        .join(SAGE_DIRECTORY_NAME)
+        .join(OUTPUTS_CACHE_FILE_NAME)
+        .to_string_lossy()
+        .into_owned()
+}
+
+// Loads the cached outputs of the stack. A missing or malformed cache is
+// treated as empty.
+fn load_outputs_cache(directory: &String) -> OutputsCache {
+    fs::read_to_string(get_outputs_cache_path(directory))
+        .ok()
+        .and_then(|raw_data| serde_json::from_str(&raw_data).ok())
+        .unwrap_or_default()
+}
+
+fn update_outputs_cache(
+    directory: &String,
+    config: &String,
+    outputs: Option<TerraformOutputs>,
+) -> Result<(), SageError> {
+    let mut cache = load_outputs_cache(directory);
+    match outputs {
+        Some(outputs) => cache.insert(config.clone(), outputs),
+        None => cache.remove(config),
+    };
+
+    let path = get_outputs_cache_path(directory);
+    if let Some(sage_directory) = Path::new(&path).parent() {
+        fs::create_dir_all(sage_directory).context(&path)?;
+    }
+    let content = serde_json::to_string_pretty(&cache).unwrap_or_default();
+    write_file_atomically(&path, &content)?;
+    Ok(())
+}
+
+// Replaces the references to the outputs of other stacks in the context of the
+// configuration with `config` name by the actual values. The referenced stacks
+// are located relative to the directory specified in `directory` parameter.
+// The outputs are taken from the cache of the stack, or read from Terraform,
+// so the stack must be initialized for the same configuration.
+pub fn resolve_stack_outputs(
+    directory: &String,
+    config: &String,
+    context: &mut TemplateContext,
+) -> Result<(), SageError> {
+    for (key, entry) in context.iter_mut() {
+        let reference = match &entry.reference {
+            Some(reference) => reference.clone(),
+            None => continue,
+        };
+
+        let stack_directory = Path::new(directory)
+            .join(&reference.stack)
+            .to_string_lossy()
+            .into_owned();
+        let outputs = get_cached_outputs(&stack_directory, config).map_err(|err| {
+            let message = format!(
+                "Unable to read the outputs of the {} stack for the {} configuration, required for the {} key: {}",
+                reference.stack, config, key, err
+            );
+            SageError::InvalidConfig(message)
+        })?;
+
+        match outputs.get(&reference.output) {
+            Some(output) => {
+                entry.value = output.value.clone();
+                entry.secret |= output.sensitive;
+                entry.reference = None;
+            }
+            None => {
+                let message = format!(
+                    "The {} stack doesn't have the {} output for the {} configuration, required for the {} key.",
+                    reference.stack, reference.output, config, key
+                );
+                return Err(SageError::InvalidConfig(message));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Replaces the references to the outputs of other stacks in the context by
+// the descriptions of the outputs, so that the context can be rendered without
+// reading the outputs, e.g. for the previews.
+pub fn mask_stack_outputs(context: &mut TemplateContext) {
+    context.values_mut().for_each(|entry| {
+        if let Some(reference) = entry.reference.take() {
+            let description = format!("(output {} of {})", reference.output, reference.stack);
+            entry.value = JsonValue::String(description);
+        }
+    });
+}
+
+// Returns the value of the output in the form suitable for printing: strings
+// are returned as is, other values are serialized to JSON. Values of the
+// sensitive outputs are masked unless the `show_sensitive` flag is set.
+pub fn format_output_value(output: &TerraformOutput, show_sensitive: bool) -> String {
+    match (output.sensitive && !show_sensitive, &output.value) {
+        (true, _) => SECRET_VALUE_MASK.to_string(),
+        (false, JsonValue::String(value)) => value.clone(),
+        (false, value) => value.to_string(),
+    }
+}
+
+// Returns the value of the output with `name` without quotes, so that it can
+// be used in shell scripts. Sensitive outputs are returned only with the
+// `show_sensitive` flag.
+pub fn format_raw_output(
+    outputs: &TerraformOutputs,
+    name: &String,
+    show_sensitive: bool,
+) -> Result<String, SageError> {
+    match outputs.get(name) {
+        Some(output) if output.sensitive && !show_sensitive => {
+            let message = format!(
+                "The {} output is sensitive, use the --show-sensitive option to print it.",
+                name
+            );
+            Err(SageError::InvalidConfig(message))
+        }
+        Some(output) => Ok(format_output_value(output, show_sensitive)),
+        None => {
+            let message = format!("Output with {} name was not found.", name);
+            Err(SageError::InvalidConfig(message))
+        }
+    }
+}
+
+// Formats the outputs as JSON document, shell exports (`env`), dotenv file or
+// Terraform variables file (`tfvars`). Values of the sensitive outputs are
+// masked unless the `show_sensitive` flag is set.
+pub fn format_outputs(outputs: &TerraformOutputs, format: &str, show_sensitive: bool) -> String {
+    let get_value = |output: &TerraformOutput| match output.sensitive && !show_sensitive {
+        true => JsonValue::String(SECRET_VALUE_MASK.to_string()),
+        false => output.value.clone(),
+    };
+    let get_variable_name = |name: &String| -> String {
+        name.chars()
+            .map(|c| match c.is_ascii_alphanumeric() {
+                true => c.to_ascii_uppercase(),
+                false => '_',
+            })
+            .collect()
+    };
+
+    match format {
+        "json" => {
+            let document: JsonMap<String, JsonValue> = outputs
+                .iter()
+                .map(|(name, output)| (name.clone(), get_value(output)))
+                .collect();
+            serde_json::to_string_pretty(&document).unwrap_or_default()
+        }
+        "env" => outputs
+            .iter()
+            .map(|(name, output)| {
+                let value = format_output_value(output, show_sensitive).replace('\'', "'\\''");
+                format!("export {}='{}'", get_variable_name(name), value)
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+        "dotenv" => outputs
+            .iter()
+            .map(|(name, output)| {
+                let value = format_output_value(output, show_sensitive)
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n");
+                format!("{}=\"{}\"", get_variable_name(name), value)
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+        _ => outputs
+            .iter()
+            .map(|(name, output)| format!("{} = {}", name, get_value(output)))
+            .collect::<Vec<String>>()
+            .join("\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use crate::outputs::{
+        forget_cached_outputs, format_outputs, format_raw_output, mask_stack_outputs,
+        resolve_stack_outputs, save_cached_outputs, TerraformOutput, TerraformOutputs,
+    };
+    use crate::template::{ContextEntry, OutputReference, TemplateContext};
+    use crate::utils::create_test_directory;
+
+    fn get_test_outputs() -> TerraformOutputs {
+        let mut outputs = TerraformOutputs::new();
+        let vpc_id = TerraformOutput {
+            value: json!("vpc-123"),
+            sensitive: false,
+        };
+        let subnets = TerraformOutput {
+            value: json!(["subnet-1", "subnet-2"]),
+            sensitive: false,
+        };
+        let db_password = TerraformOutput {
+            value: json!("it's secret"),
+            sensitive: true,
+        };
+        outputs.insert(String::from("vpc_id"), vpc_id);
+        outputs.insert(String::from("subnets"), subnets);
+        outputs.insert(String::from("db-password"), db_password);
+        outputs
+    }
+
+    #[test]
+    fn test_format_outputs() {
+        let outputs = get_test_outputs();
+
+        assert_eq!(
+            format_outputs(&outputs, "env", true),
+            "export DB_PASSWORD='it'\\''s secret'\nexport SUBNETS='[\"subnet-1\",\"subnet-2\"]'\nexport VPC_ID='vpc-123'"
+        );
+        assert_eq!(
+            format_outputs(&outputs, "dotenv", false),
+            "DB_PASSWORD=\"********\"\nSUBNETS=\"[\\\"subnet-1\\\",\\\"subnet-2\\\"]\"\nVPC_ID=\"vpc-123\""
+        );
+        assert_eq!(
+            format_outputs(&outputs, "tfvars", false),
+            "db-password = \"********\"\nsubnets = [\"subnet-1\",\"subnet-2\"]\nvpc_id = \"vpc-123\""
+        );
+    }
+
+    fn get_test_context() -> TemplateContext {
+        let mut context = TemplateContext::new();
+        let entry = ContextEntry {
+            value: json!(null),
+            source: String::from("context.toml [dev]"),
+            secret: false,
+            reference: Some(OutputReference {
+                stack: String::from("../network"),
+                output: String::from("db-password"),
+            }),
+        };
+        context.insert(String::from("password"), entry);
+        context
+    }
+
+    #[test]
+    fn test_resolve_stack_outputs_reads_cached_outputs() {
+        let path = create_test_directory("outputs-cache");
+        let network = path.join("network").to_string_lossy().into_owned();
+        let app = path.join("app").to_string_lossy().into_owned();
+        let config = String::from("dev");
+        fs::create_dir_all(&app).unwrap();
+        save_cached_outputs(&network, &config, &get_test_outputs()).unwrap();
+
+        let mut context = get_test_context();
+        resolve_stack_outputs(&app, &config, &mut context).unwrap();
+        forget_cached_outputs(&network, &config).unwrap();
+        let cache = fs::read_to_string(path.join("network/.sage/outputs.json")).unwrap();
+        fs::remove_dir_all(&path).unwrap();
+
+        assert_eq!(context["password"].value, json!("it's secret"));
+        assert_eq!(context["password"].secret, true);
+        assert_eq!(context["password"].reference, None);
+        assert_eq!(cache, "{}");
+    }
+
+    #[test]
+    fn test_mask_stack_outputs() {
+        let mut context = get_test_context();
+        mask_stack_outputs(&mut context);
+
+        assert_eq!(
+            context["password"].value,
+            json!("(output db-password of ../network)")
+        );
+        assert_eq!(context["password"].reference, None);
+    }
+
+    #[test]
+    fn test_format_raw_output() {
+        let outputs = get_test_outputs();
+
+        let vpc_id = format_raw_output(&outputs, &"vpc_id".to_string(), false).unwrap();
+        assert_eq!(vpc_id, "vpc-123");
+        let password = format_raw_output(&outputs, &"db-password".to_string(), false);
+        assert_eq!(password.is_err(), true);
+        let password = format_raw_output(&outputs, &"db-password".to_string(), true).unwrap();
+        assert_eq!(password, "it's secret");
+    }
+}
@@ -0,0 +1,368 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+
+use crate::error::SageError;
+use crate::report::{escape_markdown, escape_markdown_code};
+use crate::terraform::{CommandContext, TerraformClient};
+use crate::utils::matches_pattern;
+
+pub const PLAN_REPORT_NAME: &'static str = "plan";
+
+// Kind of the change planned for the resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChangeAction {
+    Create,
+    Update,
+    Replace,
+    Delete,
+}
+
+impl fmt::Display for ChangeAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ChangeAction::Create => "create",
+            ChangeAction::Update => "update",
+            ChangeAction::Replace => "replace",
+            ChangeAction::Delete => "delete",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// The change of a single resource in the plan, with the resource attributes
+// before and after the change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceChange {
+    pub address: String,
+    pub resource_type: String,
+    pub action: ChangeAction,
+    pub before: JsonValue,
+    pub after: JsonValue,
+}
+
+// Planned changes grouped by the action and the resource type. The values are
+// the addresses of the changed resources.
+pub type PlanSummary = BTreeMap<ChangeAction, BTreeMap<String, Vec<String>>>;
+
+// Plan document, as printed by `terraform show -json`.
+#[derive(Deserialize, Debug)]
+struct PlanDocument {
+    #[serde(default)]
+    resource_changes: Vec<PlanResourceChange>,
+    #[serde(default)]
+    resource_drift: Vec<PlanResourceChange>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PlanResourceChange {
+    address: String,
+    #[serde(rename = "type")]
+    resource_type: String,
+    change: PlanChange,
+}
+
+#[derive(Deserialize, Debug)]
+struct PlanChange {
+    actions: Vec<String>,
+    #[serde(default)]
+    before: JsonValue,
+    #[serde(default)]
+    after: JsonValue,
+}
+
+// Returns the changes from the plan file by path in `plan_file` parameter,
+// created for the configuration with `config` name in the directory specified
+// in `directory` parameter.
+pub fn get_plan_changes(
+    directory: &String,
+    config: &String,
+    plan_file: &String,
+) -> Result<Vec<ResourceChange>, SageError> {
+    let raw_data = read_plan_file(directory, config, plan_file)?;
+    parse_plan(&raw_data)
+}
+
+// Returns the resources, changed outside of Terraform, from the plan file like
+// `get_plan_changes`. The planned changes of the resources are included too,
+// because for a regular plan they show the difference from the configuration.
+pub fn get_plan_drift(
+    directory: &String,
+    config: &String,
+    plan_file: &String,
+) -> Result<Vec<ResourceChange>, SageError> {
+    let raw_data = read_plan_file(directory, config, plan_file)?;
+    parse_plan_drift(&raw_data)
+}
+
+// Returns the plan from the plan file in JSON format.
+fn read_plan_file(
+    directory: &String,
+    config: &String,
+    plan_file: &String,
+) -> Result<String, SageError> {
+    let terraform = TerraformClient::new();
+    let args = vec![String::from("-json"), plan_file.clone()];
+    let context = CommandContext::new(directory, config, None)?;
+    terraform.call_with_output("show", &args, &context)
+}
+
+// Parses the plan document in JSON format.
+fn parse_plan_document(raw_data: &str) -> Result<PlanDocument, SageError> {
+    serde_json::from_str(raw_data).map_err(|err| {
+        let message = format!("Unable to parse Terraform plan: {}.", err);
+        SageError::InvalidConfig(message)
+    })
+}
+
+// Parses the plan in JSON format and returns the changes of the resources.
+// Resources without changes and data sources reads are skipped.
+pub fn parse_plan(raw_data: &str) -> Result<Vec<ResourceChange>, SageError> {
+    let document = parse_plan_document(raw_data)?;
+    Ok(convert_resource_changes(document.resource_changes))
+}
+
+// Parses the plan in JSON format and returns the resources, changed outside
+// of Terraform, followed by the planned changes of the other resources.
+pub fn parse_plan_drift(raw_data: &str) -> Result<Vec<ResourceChange>, SageError> {
+    let document = parse_plan_document(raw_data)?;
+    let mut changes = convert_resource_changes(document.resource_drift);
+    convert_resource_changes(document.resource_changes)
+        .into_iter()
+        .for_each(|change| {
+            if !changes.iter().any(|drift| drift.address == change.address) {
+                changes.push(change);
+            }
+        });
+    Ok(changes)
+}
+
+// Converts the changes from the plan document, skipping the resources without
+// changes and data sources reads.
+fn convert_resource_changes(resources: Vec<PlanResourceChange>) -> Vec<ResourceChange> {
+    resources
+        .into_iter()
+        .filter_map(|resource| {
+            let actions: Vec<&str> = resource.change.actions.iter().map(|a| a.as_str()).collect();
+            let action = match actions.as_slice() {
+                ["create"] => ChangeAction::Create,
+                ["update"] => ChangeAction::Update,
+                ["delete"] => ChangeAction::Delete,
+                ["delete", "create"] | ["create", "delete"] => ChangeAction::Replace,
+                _ => return None,
+            };
+            Some(ResourceChange {
+                address: resource.address,
+                resource_type: resource.resource_type,
+                action,
+                before: resource.change.before,
+                after: resource.change.after,
+            })
+        })
+        .collect()
+}
+
+// Groups the changes by the action and the resource type.
+pub fn get_plan_summary(changes: &Vec<ResourceChange>) -> PlanSummary {
+    let mut summary = PlanSummary::new();
+    changes.iter().for_each(|change| {
+        summary
+            .entry(change.action)
+            .or_default()
+            .entry(change.resource_type.clone())
+            .or_default()
+            .push(change.address.clone());
+    });
+    summary
+}
+
+// Returns the total number of changes for each action, e.g. "1 to create,
+// 0 to update, 0 to replace, 2 to delete".
+pub fn format_plan_totals(summary: &PlanSummary) -> String {
+    let totals: Vec<String> = [
+        ChangeAction::Create,
+        ChangeAction::Update,
+        ChangeAction::Replace,
+        ChangeAction::Delete,
+    ]
+    .iter()
+    .map(|action| {
+        let count: usize = summary
+            .get(action)
+            .map(|resource_types| {
+                resource_types
+                    .values()
+                    .map(|addresses| addresses.len())
+                    .sum()
+            })
+            .unwrap_or(0);
+        format!("{} to {}", count, action)
+    })
+    .collect();
+    totals.join(", ")
+}
+
+// Formats the planned changes of the configuration with `config` name as
+// Markdown section with the totals and the table of the changed resources.
+pub fn format_plan_markdown(config: &String, changes: &Vec<ResourceChange>) -> String {
+    let mut lines = vec![
+        format!("### Plan for {}", escape_markdown_code(config)),
+        String::new(),
+    ];
+    if changes.is_empty() {
+        lines.push(String::from("No changes."));
+        return lines.join("\n");
+    }
+
+    let summary = get_plan_summary(changes);
+    lines.push(format!("**Plan:** {}.", format_plan_totals(&summary)));
+    lines.push(String::new());
+    lines.push(String::from("| Action | Type | Resource |"));
+    lines.push(String::from("| --- | --- | --- |"));
+    summary.iter().for_each(|(action, resource_types)| {
+        resource_types
+            .iter()
+            .for_each(|(resource_type, addresses)| {
+                addresses.iter().for_each(|address| {
+                    lines.push(format!(
+                        "| {} | {} | {} |",
+                        action,
+                        escape_markdown(resource_type),
+                        escape_markdown_code(address)
+                    ))
+                })
+            })
+    });
+    lines.join("\n")
+}
+
+// Returns the changes, which delete the resources, including replacements.
+pub fn get_destructive_changes(changes: &Vec<ResourceChange>) -> Vec<ResourceChange> {
+    changes
+        .iter()
+        .filter(|change| matches!(change.action, ChangeAction::Delete | ChangeAction::Replace))
+        .cloned()
+        .collect()
+}
+
+// Returns the destructive changes of the resources, whose type matches any of
+// the glob patterns in `resource_types`.
+pub fn get_prevented_changes(
+    changes: &Vec<ResourceChange>,
+    resource_types: &Vec<String>,
+) -> Vec<ResourceChange> {
+    get_destructive_changes(changes)
+        .into_iter()
+        .filter(|change| {
+            resource_types
+                .iter()
+                .any(|pattern| matches_pattern(pattern, &change.resource_type))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::plan::{
+        format_plan_markdown, get_destructive_changes, get_plan_summary, get_prevented_changes,
+        parse_plan, parse_plan_drift, ChangeAction,
+    };
+
+    #[test]
+    fn test_parse_plan_and_get_summary() {
+        let raw_data = r#"{
+            "format_version": "1.2",
+            "resource_changes": [
+                {"address": "aws_instance.web[0]", "type": "aws_instance", "change": {"actions": ["create"], "before": null, "after": {"ami": "ami-1"}}},
+                {"address": "aws_instance.web[1]", "type": "aws_instance", "change": {"actions": ["create"], "before": null, "after": {"ami": "ami-1"}}},
+                {"address": "aws_s3_bucket.logs", "type": "aws_s3_bucket", "change": {"actions": ["no-op"]}},
+                {"address": "aws_db_instance.main", "type": "aws_db_instance", "change": {"actions": ["delete", "create"]}},
+                {"address": "aws_security_group.old", "type": "aws_security_group", "change": {"actions": ["delete"]}},
+                {"address": "data.aws_ami.ubuntu", "type": "aws_ami", "change": {"actions": ["read"]}}
+            ]
+        }"#;
+        let changes = parse_plan(raw_data).unwrap();
+        assert_eq!(changes.len(), 4);
+        assert_eq!(changes[0].after["ami"], "ami-1");
+        assert_eq!(changes[2].action, ChangeAction::Replace);
+
+        let summary = get_plan_summary(&changes);
+        assert_eq!(summary[&ChangeAction::Create]["aws_instance"].len(), 2);
+        assert_eq!(
+            summary[&ChangeAction::Delete]["aws_security_group"],
+            vec!["aws_security_group.old"]
+        );
+        assert_eq!(summary.contains_key(&ChangeAction::Update), false);
+
+        let markdown = format_plan_markdown(&"dev".to_string(), &changes);
+        assert_eq!(
+            markdown.lines().nth(2),
+            Some("**Plan:** 2 to create, 0 to update, 1 to replace, 1 to delete.")
+        );
+        assert_eq!(
+            markdown.lines().last(),
+            Some("| delete | aws_security_group | `aws_security_group.old` |")
+        );
+    }
+
+    #[test]
+    fn test_format_plan_markdown_escapes_cells() {
+        let raw_data = r#"{
+            "resource_changes": [
+                {"address": "aws_instance.web[\"a|b<c>\"]", "type": "aws_instance", "change": {"actions": ["create"]}}
+            ]
+        }"#;
+        let changes = parse_plan(raw_data).unwrap();
+
+        let markdown = format_plan_markdown(&"eu/dev".to_string(), &changes);
+        assert_eq!(markdown.lines().next(), Some("### Plan for `eu/dev`"));
+        assert_eq!(
+            markdown.lines().last(),
+            Some(r#"| create | aws_instance | `aws_instance.web["a\|b<c>"]` |"#)
+        );
+    }
+
+    #[test]
+    fn test_get_destructive_and_prevented_changes() {
+        let raw_data = r#"{
+            "resource_changes": [
+                {"address": "aws_instance.web", "type": "aws_instance", "change": {"actions": ["update"]}},
+                {"address": "aws_db_instance.main", "type": "aws_db_instance", "change": {"actions": ["create", "delete"]}},
+                {"address": "aws_s3_bucket.logs", "type": "aws_s3_bucket", "change": {"actions": ["delete"]}}
+            ]
+        }"#;
+        let changes = parse_plan(raw_data).unwrap();
+
+        let destructive = get_destructive_changes(&changes);
+        assert_eq!(destructive.len(), 2);
+        assert_eq!(destructive[0].action, ChangeAction::Replace);
+
+        let prevented = get_prevented_changes(&changes, &vec![String::from("aws_db_*")]);
+        assert_eq!(prevented.len(), 1);
+        assert_eq!(prevented[0].address, "aws_db_instance.main");
+    }
+
+    #[test]
+    fn test_parse_plan_drift() {
+        let raw_data = r#"{
+            "resource_drift": [
+                {"address": "aws_instance.web", "type": "aws_instance", "change": {"actions": ["update"]}},
+                {"address": "aws_s3_bucket.logs", "type": "aws_s3_bucket", "change": {"actions": ["delete"]}}
+            ],
+            "resource_changes": [
+                {"address": "aws_instance.web", "type": "aws_instance", "change": {"actions": ["update"]}},
+                {"address": "aws_iam_role.app", "type": "aws_iam_role", "change": {"actions": ["create"]}}
+            ]
+        }"#;
+        let changes = parse_plan_drift(raw_data).unwrap();
+
+        let addresses: Vec<&str> = changes.iter().map(|c| c.address.as_str()).collect();
+        assert_eq!(
+            addresses,
+            vec!["aws_instance.web", "aws_s3_bucket.logs", "aws_iam_role.app"]
+        );
+        assert_eq!(changes[1].action, ChangeAction::Delete);
+    }
+}
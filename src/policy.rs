@@ -0,0 +1,311 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+
+use crate::error::SageError;
+use crate::plan::{ChangeAction, ResourceChange};
+use crate::report::{ReportCase, ReportOutcome};
+use crate::utils::{matches_pattern, parse_toml_file};
+
+pub const POLICY_FILE_NAME: &'static str = "policy.toml";
+pub const ATTRIBUTE_PATH_SEPARATOR: char = '.';
+pub const ANY_ATTRIBUTE: &'static str = "*";
+pub const POLICY_REPORT_GROUP: &'static str = "policy";
+
+// Rules, which the planned changes must follow before applying them. By
+// default the rules are stored in the `policy.toml` file in the root
+// directory of the project.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Policy {
+    pub rules: Vec<PolicyRule>,
+}
+
+// A rule, which forbids the changes of the resources, matching all of the
+// specified conditions. Resource type, address and environments are glob
+// patterns. Empty conditions match any change.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct PolicyRule {
+    pub name: String,
+    pub message: Option<String>,
+    pub resource_type: Option<String>,
+    pub address: Option<String>,
+    pub actions: Vec<String>,
+    pub attributes: Vec<AttributeMatcher>,
+    pub environments: Vec<String>,
+}
+
+// Condition for the attribute of the resource after the change. The path is
+// a dot-separated list of keys and indexes, where `*` stands for any key or
+// element of a list. The attribute must be equal to the `equals` value and
+// match the glob pattern in `matches`, when they are specified.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct AttributeMatcher {
+    pub path: String,
+    pub equals: Option<JsonValue>,
+    pub matches: Option<String>,
+}
+
+// The planned change of the resource, forbidden by the policy rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyViolation {
+    pub rule: String,
+    pub address: String,
+    pub action: ChangeAction,
+    pub message: String,
+}
+
+impl Policy {
+    // Loads the policy from the file by path in `path` parameter. If the path
+    // wasn't specified, then the `policy.toml` file in the directory specified
+    // in `directory` parameter is used when it exists.
+    pub fn load(directory: &String, path: &Option<String>) -> Result<Option<Policy>, SageError> {
+        let policy_path = match path {
+            Some(path) => path.clone(),
+            None => Path::new(directory)
+                .join(POLICY_FILE_NAME)
+                .to_string_lossy()
+                .into_owned(),
+        };
+
+        let raw_data = match fs::read_to_string(&policy_path) {
+            Ok(raw_data) => raw_data,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound && path.is_none() => {
+                return Ok(None)
+            }
+            Err(err) => return Err(SageError::Io(err, policy_path)),
+        };
+
+        let value = parse_toml_file(&policy_path, &raw_data, SageError::ConfigParse)?;
+        let policy = value.try_into::<Policy>().map_err(|err| {
+            let message = format!("The {} file is invalid: {}.", policy_path, err);
+            SageError::InvalidConfig(message)
+        })?;
+        policy.validate(&policy_path)?;
+        Ok(Some(policy))
+    }
+
+    // Checks that the rules refer only to the known actions.
+    fn validate(&self, policy_path: &String) -> Result<(), SageError> {
+        for rule in self.rules.iter() {
+            for action in rule.actions.iter() {
+                if parse_action(action).is_none() {
+                    let message = format!(
+                        "The {} rule in the {} file has unknown `{}` action. Expected one of: create, update, replace, delete.",
+                        rule.name, policy_path, action
+                    );
+                    return Err(SageError::InvalidConfig(message));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the violations of the policy rules by the planned changes of
+    // the configuration with `config` name.
+    pub fn check(&self, config: &String, changes: &Vec<ResourceChange>) -> Vec<PolicyViolation> {
+        let mut violations = Vec::new();
+        for rule in self.rules.iter().filter(|rule| rule.applies_to(config)) {
+            for change in changes.iter().filter(|change| rule.matches(change)) {
+                let message = match &rule.message {
+                    Some(message) => message.clone(),
+                    None => format!("The {} of {} is forbidden.", change.action, change.address),
+                };
+                violations.push(PolicyViolation {
+                    rule: rule.name.clone(),
+                    address: change.address.clone(),
+                    action: change.action,
+                    message,
+                });
+            }
+        }
+        violations
+    }
+
+    // Returns the results of the rules, used for the configuration with `config`
+    // name, as the report cases, one case per rule.
+    pub fn get_report_cases(
+        &self,
+        config: &String,
+        changes: &Vec<ResourceChange>,
+    ) -> Vec<ReportCase> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.applies_to(config))
+            .map(|rule| {
+                let details: Vec<String> = changes
+                    .iter()
+                    .filter(|change| rule.matches(change))
+                    .map(|change| format!("{} ({})", change.address, change.action))
+                    .collect();
+                let outcome = match details.len() {
+                    0 => ReportOutcome::Passed,
+                    count => ReportOutcome::Failed(format!("{} violation(s)", count)),
+                };
+                ReportCase {
+                    name: rule.name.clone(),
+                    group: POLICY_REPORT_GROUP.to_string(),
+                    outcome,
+                    details,
+                }
+            })
+            .collect()
+    }
+}
+
+impl PolicyRule {
+    // Checks that the rule is used for the configuration with `config` name.
+    fn applies_to(&self, config: &String) -> bool {
+        self.environments.is_empty()
+            || self
+                .environments
+                .iter()
+                .any(|pattern| matches_pattern(pattern, config))
+    }
+
+    // Checks that the planned change matches all conditions of the rule.
+    fn matches(&self, change: &ResourceChange) -> bool {
+        let matches_type = self
+            .resource_type
+            .as_ref()
+            .is_none_or(|pattern| matches_pattern(pattern, &change.resource_type));
+        let matches_address = self
+            .address
+            .as_ref()
+            .is_none_or(|pattern| matches_pattern(pattern, &change.address));
+        let matches_action = self.actions.is_empty()
+            || self
+                .actions
+                .iter()
+                .any(|action| parse_action(action) == Some(change.action));
+        // Deleted resources don't have attributes after the change, so that
+        // the attributes before the change are checked instead.
+        let attributes = match change.after {
+            JsonValue::Null => &change.before,
+            _ => &change.after,
+        };
+        let matches_attributes = self
+            .attributes
+            .iter()
+            .all(|matcher| matcher.matches(attributes));
+
+        matches_type && matches_address && matches_action && matches_attributes
+    }
+}
+
+impl AttributeMatcher {
+    // Checks that any of the attribute values by the matcher path satisfies
+    // the matcher condition.
+    fn matches(&self, attributes: &JsonValue) -> bool {
+        get_attribute_values(attributes, &self.path)
+            .iter()
+            .any(|value| {
+                let equals = self
+                    .equals
+                    .as_ref()
+                    .is_none_or(|expected| *value == expected);
+                let matches = self.matches.as_ref().is_none_or(|pattern| match value {
+                    JsonValue::String(value) => matches_pattern(pattern, value),
+                    value => matches_pattern(pattern, &value.to_string()),
+                });
+                equals && matches
+            })
+    }
+}
+
+// Returns the action of the change by its name, used in the policy rules.
+fn parse_action(name: &str) -> Option<ChangeAction> {
+    match name {
+        "create" => Some(ChangeAction::Create),
+        "update" => Some(ChangeAction::Update),
+        "replace" => Some(ChangeAction::Replace),
+        "delete" => Some(ChangeAction::Delete),
+        _ => None,
+    }
+}
+
+// Returns all values of the attributes by the dot-separated path. The `*`
+// segment expands to all elements of a list or all values of an object.
+fn get_attribute_values<'a>(attributes: &'a JsonValue, path: &str) -> Vec<&'a JsonValue> {
+    let mut values = vec![attributes];
+    for segment in path.split(ATTRIBUTE_PATH_SEPARATOR) {
+        values = values
+            .into_iter()
+            .flat_map(|value| -> Vec<&JsonValue> {
+                match (segment, value) {
+                    (ANY_ATTRIBUTE, JsonValue::Array(items)) => items.iter().collect(),
+                    (ANY_ATTRIBUTE, JsonValue::Object(items)) => items.values().collect(),
+                    (key, JsonValue::Object(items)) => items.get(key).into_iter().collect(),
+                    (key, JsonValue::Array(items)) => key
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|index| items.get(index))
+                        .into_iter()
+                        .collect(),
+                    _ => vec![],
+                }
+            })
+            .collect();
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::plan::{parse_plan, ChangeAction};
+    use crate::policy::Policy;
+    use crate::report::ReportOutcome;
+
+    #[test]
+    fn test_check_policy() {
+        let raw_policy = r#"
+            [[rules]]
+            name = "no-db-deletes"
+            resource_type = "aws_db_*"
+            actions = ["delete", "replace"]
+            environments = ["production", "prod-*"]
+
+            [[rules]]
+            name = "no-public-ingress"
+            resource_type = "aws_security_group"
+            message = "Ingress from 0.0.0.0/0 is not allowed."
+            attributes = [{ path = "ingress.*.cidr_blocks.*", equals = "0.0.0.0/0" }]
+        "#;
+        let raw_plan = r#"{
+            "resource_changes": [
+                {"address": "aws_db_instance.main", "type": "aws_db_instance", "change": {"actions": ["delete", "create"], "before": {}, "after": {}}},
+                {"address": "aws_security_group.web", "type": "aws_security_group", "change": {"actions": ["create"], "before": null, "after": {"ingress": [{"cidr_blocks": ["10.0.0.0/8"]}, {"cidr_blocks": ["0.0.0.0/0"]}]}}},
+                {"address": "aws_security_group.db", "type": "aws_security_group", "change": {"actions": ["create"], "before": null, "after": {"ingress": [{"cidr_blocks": ["10.0.0.0/8"]}]}}}
+            ]
+        }"#;
+        let policy: Policy = toml::from_str(raw_policy).unwrap();
+        let changes = parse_plan(raw_plan).unwrap();
+
+        let violations = policy.check(&"production".to_string(), &changes);
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[0].rule, "no-db-deletes");
+        assert_eq!(violations[0].action, ChangeAction::Replace);
+        assert_eq!(violations[1].address, "aws_security_group.web");
+        assert_eq!(
+            violations[1].message,
+            "Ingress from 0.0.0.0/0 is not allowed."
+        );
+
+        let violations = policy.check(&"staging".to_string(), &changes);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].rule, "no-public-ingress");
+
+        let cases = policy.get_report_cases(&"production".to_string(), &changes);
+        assert_eq!(cases.len(), 2);
+        assert_eq!(
+            cases[0].outcome,
+            ReportOutcome::Failed(String::from("1 violation(s)"))
+        );
+        assert_eq!(cases[0].details, vec!["aws_db_instance.main (replace)"]);
+    }
+}
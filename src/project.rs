@@ -0,0 +1,346 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::audit::AuditConfig;
+use crate::error::SageError;
+use crate::git::GitRules;
+use crate::hooks::{Hooks, HOOK_NAMES};
+use crate::utils::{matches_pattern, parse_toml_file};
+
+pub const PROJECT_CONFIG_FILE_NAME: &'static str = "sage.toml";
+pub const ALL_STACKS: &'static str = "all";
+
+// Settings of the project, stored in the `sage.toml` file in the root
+// directory of the project.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectConfig {
+    pub stacks: BTreeMap<String, StackConfig>,
+    pub hooks: Hooks,
+    pub environments: BTreeMap<String, EnvironmentConfig>,
+    pub audit: AuditConfig,
+    pub terraform: TerraformConfig,
+}
+
+// Settings of Terraform's invocations. With `data_per_environment` each
+// configuration keeps its Terraform working data in its own directory instead
+// of the `.terraform` directory shared by all configurations.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TerraformConfig {
+    pub data_per_environment: bool,
+}
+
+// Terraform stack of the project: a directory with its own template and
+// configurations, which depends on the other stacks.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct StackConfig {
+    pub path: String,
+    pub depends_on: Vec<String>,
+}
+
+// Settings of the configurations, which names match the glob pattern used
+// as the key in the `environments` section.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvironmentConfig {
+    pub hooks: Hooks,
+    pub git: GitRules,
+}
+
+impl ProjectConfig {
+    // Loads the project settings from the directory specified in `directory`
+    // parameter. If the settings file doesn't exist, then returns the default
+    // settings.
+    pub fn load(directory: &String) -> Result<ProjectConfig, SageError> {
+        let config_path = Path::new(directory)
+            .join(PROJECT_CONFIG_FILE_NAME)
+            .to_string_lossy()
+            .into_owned();
+
+        match fs::read_to_string(&config_path) {
+            Ok(raw_data) => {
+                let value = parse_toml_file(&config_path, &raw_data, SageError::ConfigParse)?;
+                let project = value.try_into::<ProjectConfig>().map_err(|err| {
+                    let message = format!("The {} file is invalid: {}.", config_path, err);
+                    SageError::InvalidConfig(message)
+                })?;
+                project.validate(&config_path)?;
+                Ok(project)
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(ProjectConfig::default()),
+            Err(err) => Err(SageError::Io(err, config_path)),
+        }
+    }
+
+    // Checks that only the known hooks are declared in the settings.
+    fn validate(&self, config_path: &String) -> Result<(), SageError> {
+        let unknown_hook = self
+            .environments
+            .values()
+            .map(|environment| &environment.hooks)
+            .chain(Some(&self.hooks))
+            .flat_map(|hooks| hooks.keys())
+            .find(|name| !HOOK_NAMES.contains(&name.as_str()));
+
+        match unknown_hook {
+            Some(name) => {
+                let message = format!(
+                    "The {} file has unknown `{}` hook. Expected one of: {}.",
+                    config_path,
+                    name,
+                    HOOK_NAMES.join(", ")
+                );
+                Err(SageError::InvalidConfig(message))
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Returns the commands of the hook with `name` for the configuration with
+    // `config` name: the project-wide commands go first, followed by the
+    // commands of the matching environments.
+    pub fn get_hooks(&self, config: &String, name: &str) -> Vec<String> {
+        let mut commands = self.hooks.get(name).cloned().unwrap_or_default();
+        self.environments
+            .iter()
+            .filter(|(pattern, _environment)| matches_pattern(pattern, config))
+            .filter_map(|(_pattern, environment)| environment.hooks.get(name))
+            .for_each(|hooks| commands.extend(hooks.iter().cloned()));
+        commands
+    }
+
+    // Returns the git rules of each environment section, which pattern matches
+    // the configuration with `config` name.
+    pub fn get_git_rules(&self, config: &String) -> Vec<&GitRules> {
+        self.environments
+            .iter()
+            .filter(|(pattern, _environment)| matches_pattern(pattern, config))
+            .map(|(_pattern, environment)| &environment.git)
+            .filter(|rules| !rules.is_empty())
+            .collect()
+    }
+
+    // Returns the path to the directory of the stack with `name`, relative to
+    // the project directory specified in `directory` parameter.
+    pub fn get_stack_directory(&self, directory: &String, name: &String) -> String {
+        let path = match self.stacks.get(name) {
+            Some(stack) if !stack.path.is_empty() => stack.path.clone(),
+            _ => name.clone(),
+        };
+        Path::new(directory)
+            .join(path)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    // Returns the names of the stacks listed in `selection` (comma-separated
+    // names or `all`) in the order of their dependencies, so that each stack
+    // goes after the stacks it depends on.
+    pub fn get_stacks_order(&self, selection: &String) -> Result<Vec<String>, SageError> {
+        let selected: BTreeSet<String> = match selection.as_str() {
+            ALL_STACKS => self.stacks.keys().cloned().collect(),
+            _ => selection
+                .split(',')
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect(),
+        };
+
+        if selected.is_empty() {
+            let message = format!(
+                "Stacks are not defined in the {} file.",
+                PROJECT_CONFIG_FILE_NAME
+            );
+            return Err(SageError::InvalidConfig(message));
+        }
+        for name in selected.iter() {
+            let stack = self.stacks.get(name).ok_or_else(|| {
+                let message = format!("Stack with {} name was not found.", name);
+                SageError::InvalidConfig(message)
+            })?;
+            if let Some(dependency) = stack
+                .depends_on
+                .iter()
+                .find(|dependency| !self.stacks.contains_key(*dependency))
+            {
+                let message = format!(
+                    "Stack {} depends on the unknown {} stack.",
+                    name, dependency
+                );
+                return Err(SageError::InvalidConfig(message));
+            }
+        }
+
+        let mut order: Vec<String> = Vec::new();
+        let mut pending: Vec<String> = self.stacks.keys().cloned().collect();
+        while !pending.is_empty() {
+            let (ready, blocked): (Vec<String>, Vec<String>) =
+                pending.into_iter().partition(|name| {
+                    self.stacks[name]
+                        .depends_on
+                        .iter()
+                        .all(|dependency| order.contains(dependency))
+                });
+
+            if ready.is_empty() {
+                let message = format!("Stacks have circular dependencies: {}.", blocked.join(", "));
+                return Err(SageError::InvalidConfig(message));
+            }
+            order.extend(ready);
+            pending = blocked;
+        }
+
+        Ok(order
+            .into_iter()
+            .filter(|name| selected.contains(name))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::project::{ProjectConfig, PROJECT_CONFIG_FILE_NAME};
+    use crate::utils::create_test_directory;
+
+    #[test]
+    fn test_get_stacks_order() {
+        let content = r#"
+            [stacks.app]
+            depends_on = ["database", "network"]
+
+            [stacks.database]
+            path = "stacks/database"
+            depends_on = ["network"]
+
+            [stacks.network]
+        "#;
+        let project: ProjectConfig = toml::from_str(content).unwrap();
+
+        let order = project.get_stacks_order(&"all".to_string()).unwrap();
+        assert_eq!(order, vec!["network", "database", "app"]);
+
+        let order = project
+            .get_stacks_order(&"app,network".to_string())
+            .unwrap();
+        assert_eq!(order, vec!["network", "app"]);
+
+        let directory = project.get_stack_directory(&".".to_string(), &"database".to_string());
+        assert_eq!(directory, "./stacks/database");
+        assert_eq!(
+            project.get_stacks_order(&"cache".to_string()).is_err(),
+            true
+        );
+    }
+
+    #[test]
+    fn test_get_stacks_order_returns_error_for_circular_dependencies() {
+        let content = r#"
+            [stacks.app]
+            depends_on = ["network"]
+
+            [stacks.network]
+            depends_on = ["app"]
+        "#;
+        let project: ProjectConfig = toml::from_str(content).unwrap();
+
+        assert_eq!(project.get_stacks_order(&"all".to_string()).is_err(), true);
+    }
+
+    #[test]
+    fn test_get_hooks() {
+        let content = r#"
+            [hooks]
+            before_plan = ["aws sso login"]
+
+            [environments."prod*".hooks]
+            before_plan = ["./fetch-secrets.sh"]
+            on_failure = ["notify-send failed"]
+        "#;
+        let project: ProjectConfig = toml::from_str(content).unwrap();
+
+        assert_eq!(
+            project.get_hooks(&"production".to_string(), "before_plan"),
+            vec!["aws sso login", "./fetch-secrets.sh"]
+        );
+        assert_eq!(
+            project.get_hooks(&"dev".to_string(), "before_plan"),
+            vec!["aws sso login"]
+        );
+        assert_eq!(
+            project
+                .get_hooks(&"dev".to_string(), "on_failure")
+                .is_empty(),
+            true
+        );
+    }
+
+    #[test]
+    fn test_get_git_rules() {
+        let content = r#"
+            [environments."prod*".git]
+            require_clean_tree = true
+            allowed_branches = ["main"]
+
+            [environments."prod*".hooks]
+            before_apply = ["./fetch-secrets.sh"]
+        "#;
+        let project: ProjectConfig = toml::from_str(content).unwrap();
+
+        let rules = project.get_git_rules(&"production".to_string());
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].require_clean_tree, true);
+        assert_eq!(rules[0].allowed_branches, vec!["main"]);
+        assert_eq!(rules[0].require_up_to_date_with, None);
+        assert_eq!(project.get_git_rules(&"dev".to_string()).is_empty(), true);
+        assert_eq!(
+            project.get_hooks(&"production".to_string(), "before_apply"),
+            vec!["./fetch-secrets.sh"]
+        );
+
+        let content = r#"
+            [environments."prod*".git]
+            require_clean_branch = true
+        "#;
+        assert_eq!(toml::from_str::<ProjectConfig>(content).is_err(), true);
+    }
+
+    #[test]
+    fn test_load_returns_error_for_misspelled_section() {
+        let directory = create_test_directory("misspelled-settings");
+        let content = "[hook]\nbefore_plan = [\"aws sso login\"]\n";
+        fs::write(directory.join(PROJECT_CONFIG_FILE_NAME), content).unwrap();
+
+        let result = ProjectConfig::load(&directory.to_string_lossy().into_owned());
+        fs::remove_dir_all(&directory).unwrap();
+
+        let message = result.err().map(|err| err.to_string()).unwrap_or_default();
+        assert_eq!(message.contains("unknown field `hook`"), true);
+    }
+
+    #[test]
+    fn test_load_returns_error_for_malformed_file() {
+        let directory = create_test_directory("malformed-settings");
+        let config_path = directory.join(PROJECT_CONFIG_FILE_NAME);
+        fs::write(&config_path, "[hooks]\nbefore_plan = [\"aws sso login\"\n").unwrap();
+
+        let result = ProjectConfig::load(&directory.to_string_lossy().into_owned());
+        fs::remove_dir_all(&directory).unwrap();
+
+        let message = result.err().map(|err| err.to_string()).unwrap_or_default();
+        assert_eq!(
+            message.starts_with(&format!(
+                "Failed to parse the {} settings file at line 3",
+                config_path.to_string_lossy()
+            )),
+            true
+        );
+    }
+}
@@ -0,0 +1,323 @@
+use std::fs;
+use std::path::Path;
+
+use quick_error::ResultExt;
+
+use crate::error::SageError;
+use crate::terminal::print_info;
+
+pub const JUNIT_REPORT_FORMAT: &'static str = "junit";
+pub const MARKDOWN_REPORT_FORMAT: &'static str = "markdown";
+pub const REPORT_TARGET_SEPARATOR: char = ':';
+
+// Result of a single check in the report, e.g. a configuration checked for
+// drift. Cases of the same kind share the group, and the details are printed
+// in the body of the failure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportCase {
+    pub name: String,
+    pub group: String,
+    pub outcome: ReportOutcome,
+    pub details: Vec<String>,
+}
+
+// Outcome of the check. Failures mean that the check has found problems,
+// errors mean that the check itself couldn't be completed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReportOutcome {
+    Passed,
+    Failed(String),
+    Error(String),
+}
+
+// Results of the checks and the additional parts of the Markdown report,
+// collected before the report is saved.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub cases: Vec<ReportCase>,
+    pub sections: Vec<String>,
+}
+
+// File, where the report is saved in the given format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportTarget {
+    pub format: String,
+    pub path: String,
+}
+
+// Parses the report target in the `<format>:<path>` form, passed via the
+// --report option.
+pub fn parse_report_target(value: &str) -> Result<ReportTarget, SageError> {
+    let (format, path) = value
+        .split_once(REPORT_TARGET_SEPARATOR)
+        .unwrap_or((value, ""));
+    match (format, path) {
+        (JUNIT_REPORT_FORMAT, path) | (MARKDOWN_REPORT_FORMAT, path) if !path.is_empty() => {
+            Ok(ReportTarget {
+                format: format.to_string(),
+                path: path.to_string(),
+            })
+        }
+        _ => {
+            let message = format!(
+                "The `{}` report is invalid. Expected `junit:<path>` or `markdown:<path>`.",
+                value
+            );
+            Err(SageError::InvalidConfig(message))
+        }
+    }
+}
+
+// Saves the report with the results of the checks to each of the targets.
+// The `suite` is the name of the command, the `sections` are the additional
+// parts of the Markdown report, printed before the results.
+pub fn write_reports(
+    targets: &Vec<ReportTarget>,
+    suite: &str,
+    cases: &Vec<ReportCase>,
+    sections: &Vec<String>,
+) -> Result<(), SageError> {
+    for target in targets.iter() {
+        let content = match target.format.as_str() {
+            JUNIT_REPORT_FORMAT => format_junit(suite, cases),
+            _ => format_markdown(suite, cases, sections),
+        };
+        if let Some(parent) = Path::new(&target.path).parent() {
+            fs::create_dir_all(parent).context(&target.path)?;
+        }
+        fs::write(&target.path, content + "\n").context(&target.path)?;
+        print_info(&format!(
+            "The {} report was saved to {}.",
+            target.format, target.path
+        ));
+    }
+    Ok(())
+}
+
+// Formats the results of the checks as Markdown document, suitable for pull
+// request comments. The `sections` are printed before the table of results.
+pub fn format_markdown(suite: &str, cases: &Vec<ReportCase>, sections: &Vec<String>) -> String {
+    let mut lines = vec![format!("## terraform-sage {}", suite), String::new()];
+    sections.iter().for_each(|section| {
+        lines.push(section.trim_end().to_string());
+        lines.push(String::new());
+    });
+
+    lines.push(String::from("| Check | Result | Details |"));
+    lines.push(String::from("| --- | --- | --- |"));
+    for case in cases.iter() {
+        let name = match case.group == suite {
+            true => case.name.clone(),
+            false => format!("{}: {}", case.group, case.name),
+        };
+        let result = match &case.outcome {
+            ReportOutcome::Passed => String::from(":white_check_mark: passed"),
+            ReportOutcome::Failed(message) => format!(":x: {}", message),
+            ReportOutcome::Error(message) => format!(":warning: {}", message),
+        };
+        let details: Vec<String> = case
+            .details
+            .iter()
+            .map(|line| escape_markdown_code(line.trim()))
+            .collect();
+        lines.push(format!(
+            "| {} | {} | {} |",
+            escape_markdown(&name),
+            escape_markdown(&result),
+            details.join("<br>")
+        ));
+    }
+    lines.join("\n")
+}
+
+// Formats the results of the checks as JUnit XML report with a single test
+// suite, named after the command in `suite` parameter.
+pub fn format_junit(suite: &str, cases: &Vec<ReportCase>) -> String {
+    let count_outcomes = |predicate: fn(&ReportOutcome) -> bool| {
+        cases.iter().filter(|case| predicate(&case.outcome)).count()
+    };
+    let failures = count_outcomes(|outcome| matches!(outcome, ReportOutcome::Failed(_)));
+    let errors = count_outcomes(|outcome| matches!(outcome, ReportOutcome::Error(_)));
+
+    let mut lines = vec![
+        String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#),
+        String::from("<testsuites>"),
+        format!(
+            r#"  <testsuite name="{}" tests="{}" failures="{}" errors="{}">"#,
+            escape_xml(suite),
+            cases.len(),
+            failures,
+            errors
+        ),
+    ];
+    for case in cases.iter() {
+        let opening_tag = format!(
+            r#"    <testcase name="{}" classname="{}""#,
+            escape_xml(&case.name),
+            escape_xml(&case.group)
+        );
+        let (tag, message) = match &case.outcome {
+            ReportOutcome::Passed => {
+                lines.push(format!("{}/>", opening_tag));
+                continue;
+            }
+            ReportOutcome::Failed(message) => ("failure", message),
+            ReportOutcome::Error(message) => ("error", message),
+        };
+        lines.push(format!("{}>", opening_tag));
+        lines.push(format!(
+            r#"      <{} message="{}">{}</{}>"#,
+            tag,
+            escape_xml(message),
+            escape_xml(&case.details.join("\n")),
+            tag
+        ));
+        lines.push(String::from("    </testcase>"));
+    }
+    lines.push(String::from("  </testsuite>"));
+    lines.push(String::from("</testsuites>"));
+    lines.join("\n")
+}
+
+// Replaces the characters, which have a special meaning in XML, by entities.
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// Escapes the characters, which break the table cell in Markdown or are
+// taken as HTML tags. Line breaks are replaced by HTML tags.
+pub fn escape_markdown(value: &str) -> String {
+    value
+        .trim()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('|', "\\|")
+        .replace('\n', "<br>")
+}
+
+// Formats the value as Markdown code span, which may be placed in the table
+// cell. HTML entities aren't decoded in the code spans, so only the backticks
+// (by the longer delimiter) and pipes are escaped, while line breaks are
+// replaced by spaces.
+pub fn escape_markdown_code(value: &str) -> String {
+    let longest_backticks = value
+        .split(|c| c != '`')
+        .map(|backticks| backticks.len())
+        .max()
+        .unwrap_or(0);
+    let delimiter = "`".repeat(longest_backticks + 1);
+    let padding = match value.starts_with('`') || value.ends_with('`') {
+        true => " ",
+        false => "",
+    };
+    format!(
+        "{}{}{}{}{}",
+        delimiter,
+        padding,
+        value.replace('|', "\\|").replace('\n', " "),
+        padding,
+        delimiter
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::report::{
+        escape_markdown_code, format_junit, format_markdown, parse_report_target, ReportCase,
+        ReportOutcome,
+    };
+
+    fn get_test_cases() -> Vec<ReportCase> {
+        vec![
+            ReportCase {
+                name: String::from("dev"),
+                group: String::from("drift"),
+                outcome: ReportOutcome::Passed,
+                details: vec![],
+            },
+            ReportCase {
+                name: String::from("production"),
+                group: String::from("drift"),
+                outcome: ReportOutcome::Failed(String::from("1 resource(s) drifted")),
+                details: vec![String::from("aws_instance.web[\"a\"] (update)")],
+            },
+            ReportCase {
+                name: String::from("staging"),
+                group: String::from("drift"),
+                outcome: ReportOutcome::Error(String::from("<not initialized>")),
+                details: vec![],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_format_junit() {
+        let cases = get_test_cases();
+
+        let report = format_junit("drift", &cases);
+        assert_eq!(
+            report,
+            [
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                "<testsuites>",
+                r#"  <testsuite name="drift" tests="3" failures="1" errors="1">"#,
+                r#"    <testcase name="dev" classname="drift"/>"#,
+                r#"    <testcase name="production" classname="drift">"#,
+                r#"      <failure message="1 resource(s) drifted">aws_instance.web[&quot;a&quot;] (update)</failure>"#,
+                "    </testcase>",
+                r#"    <testcase name="staging" classname="drift">"#,
+                r#"      <error message="&lt;not initialized&gt;"></error>"#,
+                "    </testcase>",
+                "  </testsuite>",
+                "</testsuites>",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_format_markdown() {
+        let mut cases = get_test_cases();
+        cases[2].group = String::from("policy");
+        let sections = vec![String::from("### Summary\n")];
+
+        let report = format_markdown("drift", &cases, &sections);
+        assert_eq!(
+            report,
+            [
+                "## terraform-sage drift",
+                "",
+                "### Summary",
+                "",
+                "| Check | Result | Details |",
+                "| --- | --- | --- |",
+                "| dev | :white_check_mark: passed |  |",
+                "| production | :x: 1 resource(s) drifted | `aws_instance.web[\"a\"] (update)` |",
+                "| policy: staging | :warning: &lt;not initialized&gt; |  |",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_escape_markdown_code() {
+        assert_eq!(escape_markdown_code("a < b && c"), "`a < b && c`");
+        assert_eq!(escape_markdown_code("a || b"), "`a \\|\\| b`");
+        assert_eq!(escape_markdown_code("run `cmd`"), "`` run `cmd` ``");
+    }
+
+    #[test]
+    fn test_parse_report_target() {
+        let target = parse_report_target("junit:reports/plan.xml").unwrap();
+        assert_eq!(target.format, "junit");
+        assert_eq!(target.path, "reports/plan.xml");
+        assert_eq!(parse_report_target("markdown:").is_err(), true);
+        assert_eq!(parse_report_target("html:report.html").is_err(), true);
+    }
+}
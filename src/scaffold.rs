@@ -0,0 +1,783 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use quick_error::ResultExt;
+use toml::value::Table as TomlTable;
+use toml::Value as TomlValue;
+use toml_edit::{DocumentMut, Item as TomlItem, Table as TomlEditTable, TableLike};
+
+use crate::error::SageError;
+use crate::hcl::{parse_hcl, HclItem};
+use crate::manifest::Manifest;
+use crate::metadata::METADATA_SECTION_NAME;
+use crate::template::{
+    format_section_name, format_toml_key, get_context_section, is_generated_file,
+    read_context_file, render, CONTEXT_FILE_PATH,
+};
+use crate::terminal::{print_info, print_warning};
+use crate::utils::{
+    get_configs, get_data_directory, is_correct_config, write_file_atomically,
+    CONFIG_DIRECTORY_NAME, CONFIG_NAME_SEPARATOR, DATA_DIRECTORY_NAME,
+};
+
+pub const SUPPORTED_BACKENDS: &'static [&str; 3] = &["local", "s3", "gcs"];
+pub const DEFAULT_ENVIRONMENTS: &'static [&str; 3] = &["dev", "staging", "production"];
+pub const DEFAULT_VARIABLES_FILE_NAME: &'static str = "variables.tfvars";
+
+const GITIGNORE_TEMPLATE: &'static str = ".sage/
+.terraform/
+main.tf
+main-*.tf
+*.tfstate
+*.tfstate.backup
+";
+
+const VARIABLES_TEMPLATE: &'static str = "variable \"environment\" {
+  type    = string
+  default = \"unknown\"
+}
+";
+
+const LOCAL_BACKEND_TEMPLATE: &'static str = "terraform {
+  backend \"local\" {
+    path = \"terraform-{{CONFIG_NAME}}.tfstate\"
+  }
+}
+";
+
+const S3_BACKEND_TEMPLATE: &'static str = "terraform {
+  backend \"s3\" {
+    bucket = \"{{state_bucket}}\"
+    key    = \"terraform/state-{{CONFIG_NAME}}.tfstate\"
+    region = \"{{region}}\"
+  }
+}
+
+provider \"aws\" {
+  region = \"{{region}}\"
+}
+";
+
+const GCS_BACKEND_TEMPLATE: &'static str = "terraform {
+  backend \"gcs\" {
+    bucket = \"{{state_bucket}}\"
+    prefix = \"terraform/state-{{CONFIG_NAME}}\"
+  }
+}
+
+provider \"google\" {
+  region = \"{{region}}\"
+}
+";
+
+// Creates a new project in the directory specified in `directory` parameter
+// with the template for the given `backend`, the variables module and the
+// configuration directories for the default environments. Returns the list
+// of created files.
+pub fn create_project(directory: &String, backend: &String) -> Result<Vec<String>, SageError> {
+    let project_directory = Path::new(directory);
+    let is_empty = match fs::read_dir(project_directory) {
+        Ok(mut entries) => entries.next().is_none(),
+        Err(_) => true,
+    };
+    if !is_empty {
+        let message = format!(
+            "The {} directory already exists and is not empty.",
+            directory
+        );
+        return Err(SageError::InvalidConfig(message));
+    }
+
+    let (template, context) = match backend.as_str() {
+        "s3" => (
+            S3_BACKEND_TEMPLATE,
+            "state_bucket = \"terraform-state\"\nregion = \"us-east-1\"\n",
+        ),
+        "gcs" => (
+            GCS_BACKEND_TEMPLATE,
+            "state_bucket = \"terraform-state\"\nregion = \"us-central1\"\n",
+        ),
+        "local" => (LOCAL_BACKEND_TEMPLATE, ""),
+        _ => {
+            let message = format!(
+                "Backend {} is not supported. Use one of: {}.",
+                backend,
+                SUPPORTED_BACKENDS.join(", ")
+            );
+            return Err(SageError::InvalidConfig(message));
+        }
+    };
+
+    let mut files = vec![
+        (String::from(".gitignore"), GITIGNORE_TEMPLATE.to_string()),
+        (String::from("main.tpl"), template.to_string()),
+        (String::from("variables.tf"), VARIABLES_TEMPLATE.to_string()),
+    ];
+    let mut context_sections = Vec::new();
+    for environment in DEFAULT_ENVIRONMENTS.iter() {
+        let path = Path::new(CONFIG_DIRECTORY_NAME)
+            .join(environment)
+            .join(DEFAULT_VARIABLES_FILE_NAME);
+        let content = format!("environment = \"{}\"\n", environment);
+        files.push((path.to_string_lossy().into_owned(), content));
+        context_sections.push(format!("[{}]\n{}", environment, context));
+    }
+    files.push((CONTEXT_FILE_PATH.to_string(), context_sections.join("\n")));
+
+    let mut created_files = Vec::new();
+    for (path, content) in files {
+        let path = project_directory.join(path);
+        let filename = path.to_string_lossy().into_owned();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context(&filename)?;
+        }
+        fs::write(&path, content).context(&filename)?;
+        created_files.push(filename);
+    }
+
+    Ok(created_files)
+}
+
+// Checks that the given name can be used as the configuration name. Names of
+// the nested configurations are separated with slashes, e.g. `eu-west-1/prod`.
+pub fn validate_config_name(name: &String) -> Result<(), SageError> {
+    let is_valid = name.split(CONFIG_NAME_SEPARATOR).all(|segment| {
+        !segment.is_empty()
+            && !segment.starts_with('.')
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    });
+    let is_reserved = name
+        .split(CONFIG_NAME_SEPARATOR)
+        .skip(1)
+        .any(|segment| segment == METADATA_SECTION_NAME);
+
+    match (is_valid, is_reserved) {
+        (true, false) => Ok(()),
+        (false, _) => {
+            let message = format!(
+                "Configuration name {} must contain only letters, digits, dots, dashes and underscores.",
+                name
+            );
+            Err(SageError::InvalidConfig(message))
+        }
+        (true, true) => {
+            let message = format!(
+                "Configuration name {} can't use the reserved {} name for nested directories.",
+                name, METADATA_SECTION_NAME
+            );
+            Err(SageError::InvalidConfig(message))
+        }
+    }
+}
+
+// Checks that the configuration with the given name can be created next to
+// the existing configurations. Existing configurations can't become parents
+// of the new one, and vice versa.
+fn check_config_name_is_free(
+    name: &String,
+    configs: &HashMap<String, String>,
+) -> Result<(), SageError> {
+    let is_conflicting = configs.keys().any(|config| {
+        let is_parent = name.starts_with(&format!("{}{}", config, CONFIG_NAME_SEPARATOR));
+        let is_child = config.starts_with(&format!("{}{}", name, CONFIG_NAME_SEPARATOR));
+        config == name || is_parent || is_child
+    });
+
+    match is_conflicting {
+        false => Ok(()),
+        true => {
+            let message = format!(
+                "Configuration with {} name already exists or conflicts with the existing ones.",
+                name
+            );
+            Err(SageError::InvalidConfig(message))
+        }
+    }
+}
+
+// Creates a new configuration with `name` in the project, located in the
+// directory specified in `directory` parameter. When the `variable_files` are
+// passed, then they are copied into the new configuration directory. Files
+// from several directories (the layers of a nested configuration) are
+// prefixed with their order, so that they are applied in the same order. The
+// section in the context file is created from the sections of the `from`
+// configuration and its parent directories or empty otherwise. Returns the
+// path to the new configuration.
+pub fn add_environment(
+    directory: &String,
+    name: &String,
+    from: &Option<String>,
+    variable_files: &Vec<String>,
+) -> Result<String, SageError> {
+    validate_config_name(name)?;
+    let configs = get_configs(directory)?;
+    check_config_name_is_free(name, &configs)?;
+
+    let config_directory = Path::new(directory).join(CONFIG_DIRECTORY_NAME).join(name);
+    let config_path = config_directory.to_string_lossy().into_owned();
+    fs::create_dir_all(&config_directory).context(&config_path)?;
+
+    let source_directories: Vec<Option<&Path>> = variable_files
+        .iter()
+        .map(|variable_file| Path::new(variable_file).parent())
+        .collect();
+    let is_layered = source_directories
+        .iter()
+        .any(|source_directory| *source_directory != source_directories[0]);
+    for (index, variable_file) in variable_files.iter().enumerate() {
+        let file_name = Path::new(variable_file)
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let path = match is_layered {
+            true => config_directory.join(format!("{:02}-{}", index + 1, file_name)),
+            false => config_directory.join(file_name),
+        };
+        fs::copy(variable_file, &path).context(variable_file.as_str())?;
+    }
+    if variable_files.is_empty() {
+        let path = config_directory.join(DEFAULT_VARIABLES_FILE_NAME);
+        let content = format!("# Variables for the {} configuration\n", name);
+        fs::write(&path, content).context(&config_path)?;
+    }
+
+    let section = match (from, read_context_file(directory)?) {
+        (Some(from), Some((_toml_path, toml_root))) => {
+            get_layered_context_section(&toml_root, from, &configs)
+        }
+        _ => TomlTable::new(),
+    };
+    append_context_section(directory, name, section)?;
+
+    Ok(config_path)
+}
+
+// Returns the definitions of the context file section for the configuration
+// with `config` name, merged with the sections of its parent directories.
+// The sections of the nested configurations and the metadata of the parent
+// directories aren't included.
+fn get_layered_context_section(
+    toml_root: &TomlValue,
+    config: &String,
+    configs: &HashMap<String, String>,
+) -> TomlTable {
+    let segments: Vec<&str> = config.split(CONFIG_NAME_SEPARATOR).collect();
+    let mut section = TomlTable::new();
+    for index in 1..=segments.len() {
+        let section_config = segments[..index].join(CONFIG_NAME_SEPARATOR);
+        let table = match get_context_section(toml_root, &section_config).and_then(|s| s.as_table())
+        {
+            Some(table) => table,
+            None => continue,
+        };
+
+        let prefix = format!("{}{}", section_config, CONFIG_NAME_SEPARATOR);
+        let nested_sections: Vec<&str> = configs
+            .keys()
+            .filter_map(|name| name.strip_prefix(&prefix))
+            .filter_map(|name| name.split(CONFIG_NAME_SEPARATOR).next())
+            .chain(std::iter::once(METADATA_SECTION_NAME))
+            .collect();
+        for (key, value) in table.iter() {
+            let is_parent = index < segments.len();
+            if !is_parent || !nested_sections.contains(&key.as_str()) {
+                section.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    section
+}
+
+// Appends the section with the given name and definitions to the end of the
+// context file, so that the existing content stays untouched.
+pub fn append_context_section(
+    directory: &String,
+    name: &String,
+    section: TomlTable,
+) -> Result<(), SageError> {
+    let toml_path = Path::new(directory)
+        .join(CONTEXT_FILE_PATH)
+        .to_string_lossy()
+        .into_owned();
+    let mut content = fs::read_to_string(&toml_path).unwrap_or_default();
+    let section_content = format_context_section(&format_section_name(name), &section);
+
+    if !content.is_empty() && !content.ends_with("\n\n") {
+        content.push_str(if content.ends_with('\n') {
+            "\n"
+        } else {
+            "\n\n"
+        });
+    }
+    content.push_str(&section_content);
+    fs::write(&toml_path, content).context(&toml_path)?;
+    Ok(())
+}
+
+// Formats the section with the given name and its nested tables, so that
+// the headers of the nested tables contain the full path to them.
+fn format_context_section(section_name: &String, section: &TomlTable) -> String {
+    let (tables, values): (TomlTable, TomlTable) = section
+        .clone()
+        .into_iter()
+        .partition(|(_key, value)| value.is_table());
+
+    let mut content = format!(
+        "[{}]\n{}",
+        section_name,
+        toml::to_string(&values).unwrap_or_default()
+    );
+    for (key, table) in tables.iter() {
+        if let TomlValue::Table(table) = table {
+            let table_name = format!("{}.{}", section_name, format_toml_key(key));
+            content.push('\n');
+            content.push_str(&format_context_section(&table_name, table));
+        }
+    }
+    content
+}
+
+// Renames the configuration with `name` to `new_name` in the project, located
+// in the directory specified in `directory` parameter. Moves the configuration
+// directory and the Terraform data directory, renames the section in the
+// context file and deletes the files generated for the old name. Returns the
+// new path to the configuration.
+//
+// The backend blocks, rendered from the template with `template` name for the
+// configuration and its nested configurations, must stay the same, otherwise
+// the renamed configurations would use another Terraform state. When they
+// change, the rename is reverted, unless the `force` flag is set.
+pub fn rename_environment(
+    directory: &String,
+    name: &String,
+    new_name: &String,
+    template: &String,
+    force: bool,
+) -> Result<String, SageError> {
+    validate_config_name(new_name)?;
+    let mut configs = get_configs(directory)?;
+    is_correct_config(name, configs.clone())?;
+    let config_directory = configs.remove(name).unwrap_or_default();
+    check_config_name_is_free(new_name, &configs)?;
+
+    let is_backend_checked = !force && Path::new(directory).join(template).exists();
+    if !force && !is_backend_checked {
+        print_warning(&format!(
+            "The {} template wasn't found, so the backend of the renamed configuration wasn't checked.",
+            template
+        ));
+    }
+    let backends = match is_backend_checked {
+        true => get_backend_blocks(directory, name, template)?,
+        false => vec![],
+    };
+
+    let config_path = move_environment(directory, &config_directory, name, new_name)?;
+    if is_backend_checked {
+        match get_backend_blocks(directory, new_name, template) {
+            Ok(new_backends) if new_backends == backends => {}
+            result => {
+                let configs = get_configs(directory)?;
+                move_environment(directory, &configs[new_name], new_name, name)?;
+                result?;
+                let message = format!(
+                    "The backend of the {} configuration depends on its name, so the renamed configuration would use another Terraform state. Move the state with `terraform state pull` and `terraform state push` and use the --force option.",
+                    name
+                );
+                return Err(SageError::InvalidConfig(message));
+            }
+        }
+    }
+
+    delete_generated_files(directory, name)?;
+    Ok(config_path)
+}
+
+// Moves the configuration directory by path in `config_directory` parameter
+// and the Terraform data directory of the configuration with `name` to the
+// `new_name`, and renames its section in the context file. Returns the new
+// path to the configuration.
+fn move_environment(
+    directory: &String,
+    config_directory: &String,
+    name: &String,
+    new_name: &String,
+) -> Result<String, SageError> {
+    let new_config_directory = Path::new(directory)
+        .join(CONFIG_DIRECTORY_NAME)
+        .join(new_name);
+    let config_path = new_config_directory.to_string_lossy().into_owned();
+    move_directory(config_directory, &config_path)?;
+
+    let data_directory = get_data_directory(directory, name);
+    if Path::new(&data_directory).exists() {
+        move_directory(&data_directory, &get_data_directory(directory, new_name))?;
+    }
+
+    update_context_section(directory, name, Some(new_name))?;
+    Ok(config_path)
+}
+
+// Returns the backend and cloud blocks of the `terraform` block, rendered from
+// the template with `template` name for the configuration with `name` and for
+// each of its nested configurations. The blocks are listed together with the
+// name of the configuration relative to `name`.
+fn get_backend_blocks(
+    directory: &String,
+    name: &String,
+    template: &String,
+) -> Result<Vec<(String, Vec<HclItem>)>, SageError> {
+    let prefix = format!("{}{}", name, CONFIG_NAME_SEPARATOR);
+    let mut config_names: Vec<String> = get_configs(directory)?
+        .into_keys()
+        .filter(|config| config == name || config.starts_with(&prefix))
+        .collect();
+    config_names.sort();
+
+    let mut backends = Vec::new();
+    for config in config_names {
+        let module = render(directory, &config, template)?;
+        let blocks = parse_hcl(template, &module)?
+            .into_iter()
+            .filter_map(|item| match item {
+                HclItem::Block(block_type, _labels, body) if block_type == "terraform" => {
+                    Some(body)
+                }
+                _ => None,
+            })
+            .flatten()
+            .filter(|item| {
+                matches!(item, HclItem::Block(block_type, _, _) if block_type == "backend" || block_type == "cloud")
+            })
+            .collect();
+        backends.push((config[name.len()..].to_string(), blocks));
+    }
+    Ok(backends)
+}
+
+// Removes the configuration with `name` from the project, located in the
+// directory specified in `directory` parameter, together with its section in
+// the context file, the generated files and the Terraform data directory.
+pub fn remove_environment(directory: &String, name: &String) -> Result<(), SageError> {
+    let configs = get_configs(directory)?;
+    is_correct_config(name, configs.clone())?;
+
+    delete_generated_files(directory, name)?;
+    update_context_section(directory, name, None)?;
+
+    let data_directory = get_data_directory(directory, name);
+    if Path::new(&data_directory).exists() {
+        fs::remove_dir_all(&data_directory).context(&data_directory)?;
+        remove_empty_parents(&data_directory);
+    }
+    fs::remove_dir_all(&configs[name]).context(&configs[name])?;
+    remove_empty_parents(&configs[name]);
+    Ok(())
+}
+
+// Moves the directory by path in `source` parameter to `destination`, creating
+// the missing parent directories. Parents of the source directory that became
+// empty are removed.
+fn move_directory(source: &String, destination: &String) -> Result<(), SageError> {
+    if let Some(parent) = Path::new(destination).parent() {
+        fs::create_dir_all(parent).context(destination)?;
+    }
+    fs::rename(source, destination).context(source)?;
+    remove_empty_parents(source);
+    Ok(())
+}
+
+// Removes the empty parent directories of the nested configuration by path in
+// `path` parameter, so that they aren't treated as configurations.
+fn remove_empty_parents(path: &String) {
+    for parent in Path::new(path).ancestors().skip(1) {
+        let name = parent.file_name().unwrap_or_default();
+        let is_root = name == CONFIG_DIRECTORY_NAME || name == DATA_DIRECTORY_NAME;
+        if is_root || fs::remove_dir(parent).is_err() {
+            break;
+        }
+    }
+}
+
+// Renames the section of the configuration with `name` (and its sub-tables) in
+// the context file to `new_name`, or removes it when the new name is not passed.
+// The section is edited in the parsed document, so it can be declared with the
+// table header, dotted keys or the inline table. The rest of the file,
+// including comments and formatting, stays untouched.
+fn update_context_section(
+    directory: &String,
+    name: &String,
+    new_name: Option<&String>,
+) -> Result<(), SageError> {
+    let toml_path = Path::new(directory)
+        .join(CONTEXT_FILE_PATH)
+        .to_string_lossy()
+        .into_owned();
+    let content = match fs::read_to_string(&toml_path) {
+        Ok(content) => content,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(SageError::Io(err, toml_path)),
+    };
+    let mut document = content.parse::<DocumentMut>().map_err(|err| {
+        let message = format!("The {} file is invalid: {}.", toml_path, err);
+        SageError::InvalidConfig(message)
+    })?;
+
+    let section = match take_context_section(document.as_table_mut(), name) {
+        Some(section) => section,
+        None => return Ok(()),
+    };
+    if let Some(new_name) = new_name {
+        insert_context_section(document.as_table_mut(), new_name, section).ok_or_else(|| {
+            let message = format!(
+                "The {} file has a value in place of the [{}] section.",
+                toml_path,
+                format_section_name(new_name)
+            );
+            SageError::InvalidConfig(message)
+        })?;
+    }
+    write_file_atomically(&toml_path, &document.to_string())?;
+    Ok(())
+}
+
+// Removes the section of the configuration with `config` name from the parsed
+// context file and returns it, if it exists.
+fn take_context_section(root: &mut TomlEditTable, config: &String) -> Option<TomlItem> {
+    let segments: Vec<&str> = config.split(CONFIG_NAME_SEPARATOR).collect();
+    let (key, parents) = segments.split_last()?;
+    let parent = parents
+        .iter()
+        .try_fold(root as &mut dyn TableLike, |table, segment| {
+            table.get_mut(segment)?.as_table_like_mut()
+        })?;
+    match parent.get(key)?.is_table_like() {
+        true => parent.remove(key),
+        false => None,
+    }
+}
+
+// Inserts the `section` of the configuration with `config` name to the parsed
+// context file, creating the missing parent tables. Returns `None`, when any
+// of the parents is not a table.
+fn insert_context_section(
+    root: &mut TomlEditTable,
+    config: &String,
+    section: TomlItem,
+) -> Option<()> {
+    let segments: Vec<&str> = config.split(CONFIG_NAME_SEPARATOR).collect();
+    let (key, parents) = segments.split_last()?;
+    let is_dotted = section.as_table().is_none_or(|table| table.is_dotted());
+    let mut table: &mut dyn TableLike = root;
+    for segment in parents {
+        table = table
+            .entry(segment)
+            .or_insert_with(|| {
+                let mut parent = TomlEditTable::new();
+                parent.set_implicit(true);
+                parent.set_dotted(is_dotted);
+                TomlItem::Table(parent)
+            })
+            .as_table_like_mut()?;
+    }
+    table.insert(key, section);
+    Some(())
+}
+
+// Deletes the files generated for the configuration with `config` name in the
+// directory specified in `directory` parameter. Files modified after the
+// generation are left untouched.
+fn delete_generated_files(directory: &String, config: &String) -> Result<(), SageError> {
+    let mut manifest = Manifest::load(Path::new(directory));
+    let file_names: Vec<String> = manifest
+        .files
+        .iter()
+        .filter(|(_file_name, entry)| &entry.config == config)
+        .map(|(file_name, _entry)| file_name.clone())
+        .collect();
+    if file_names.is_empty() {
+        return Ok(());
+    }
+
+    for file_name in file_names {
+        let filepath = Path::new(directory)
+            .join(&file_name)
+            .to_string_lossy()
+            .into_owned();
+        match (Path::new(&filepath).exists(), is_generated_file(&filepath)) {
+            (true, true) => {
+                fs::remove_file(&filepath).context(&filepath)?;
+                print_info(&format!("The generated {} file was deleted.", filepath));
+            }
+            (true, false) => print_warning(&format!(
+                "The {} file was modified after generation and will not be deleted.",
+                filepath
+            )),
+            _ => {}
+        }
+        manifest.remove(&filepath);
+    }
+    manifest.save()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::scaffold::{
+        add_environment, create_project, remove_environment, rename_environment,
+        update_context_section,
+    };
+    use crate::template::{generate_from_template, get_template_context};
+    use crate::terraform::TerraformClient;
+    use crate::utils::{create_test_directory, get_configs, get_data_directory};
+
+    #[test]
+    fn test_create_project_and_add_environment() {
+        let directory = create_test_directory("scaffold")
+            .to_string_lossy()
+            .into_owned();
+
+        let files = create_project(&directory, &"s3".to_string()).unwrap();
+        assert_eq!(files.len(), 7);
+        assert_eq!(create_project(&directory, &"s3".to_string()).is_err(), true);
+
+        let configs = get_configs(&directory).unwrap();
+        let variable_files = vec![format!("{}/variables.tfvars", configs["staging"])];
+        let from = Some("staging".to_string());
+        let name = "qa".to_string();
+        add_environment(&directory, &name, &from, &variable_files).unwrap();
+        let result = add_environment(&directory, &name, &from, &variable_files);
+        assert_eq!(result.is_err(), true);
+
+        let context = get_template_context(&directory, &name).unwrap();
+        let configs = get_configs(&directory).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(configs.len(), 4);
+        assert_eq!(context["region"].value, "us-east-1");
+    }
+
+    #[test]
+    fn test_add_environment_from_nested_configuration() {
+        let path = create_test_directory("nested-from");
+        let directory = path.to_string_lossy().into_owned();
+        fs::create_dir_all(path.join("configs/eu/dev")).unwrap();
+        fs::write(path.join("configs/eu/common.tfvars"), "region = \"eu\"\n").unwrap();
+        fs::write(path.join("configs/eu/dev/variables.tfvars"), "size = 1\n").unwrap();
+        let context = "[eu]\nregion = \"eu-west-1\"\nzone = \"a\"\n\n\
+                       [eu.meta]\ndescription = \"Europe\"\n\n\
+                       [eu.dev]\nzone = \"b\"\n";
+        fs::write(path.join("configs/context.toml"), context).unwrap();
+
+        let configs = get_configs(&directory).unwrap();
+        let variable_files = TerraformClient::new().get_config_variable_files(&configs["eu/dev"]);
+        let from = Some("eu/dev".to_string());
+        let name = "qa".to_string();
+        add_environment(&directory, &name, &from, &variable_files).unwrap();
+
+        let mut files: Vec<String> = fs::read_dir(path.join("configs/qa"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        let context = get_template_context(&directory, &name).unwrap();
+        let content = fs::read_to_string(path.join("configs/context.toml")).unwrap();
+        fs::remove_dir_all(&path).unwrap();
+
+        assert_eq!(files, vec!["01-common.tfvars", "02-variables.tfvars"]);
+        assert_eq!(context["region"].value, "eu-west-1");
+        assert_eq!(context["zone"].value, "b");
+        assert_eq!(content.contains("[qa.meta]"), false);
+    }
+
+    #[test]
+    fn test_rename_and_remove_environment() {
+        let directory = create_test_directory("environments")
+            .to_string_lossy()
+            .into_owned();
+        create_project(&directory, &"gcs".to_string()).unwrap();
+
+        let (name, new_name) = ("dev".to_string(), "development".to_string());
+        let template = format!("{}/main.tpl", directory);
+        let out = format!("{}/main.tf", directory);
+        generate_from_template(&directory, &name, &template, &out, false).unwrap();
+        fs::create_dir_all(get_data_directory(&directory, &name)).unwrap();
+
+        let template_name = "main.tpl".to_string();
+        let result = rename_environment(&directory, &name, &new_name, &template_name, false);
+        let is_rename_reverted = get_configs(&directory).unwrap().contains_key(&name)
+            && fs::metadata(get_data_directory(&directory, &name)).is_ok();
+
+        fs::write(&template, "terraform {\n  backend \"local\" {}\n}\n").unwrap();
+        rename_environment(&directory, &name, &new_name, &template_name, false).unwrap();
+        let is_generated_file_exists = fs::metadata(&out).is_ok();
+        let is_data_directory_moved =
+            fs::metadata(get_data_directory(&directory, &new_name)).is_ok();
+        let context = get_template_context(&directory, &new_name).unwrap();
+
+        remove_environment(&directory, &"staging".to_string()).unwrap();
+        let configs = get_configs(&directory).unwrap();
+        let context_file =
+            fs::read_to_string(format!("{}/configs/context.toml", directory)).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(result.is_err(), true);
+        assert_eq!(is_rename_reverted, true);
+        assert_eq!(is_generated_file_exists, false);
+        assert_eq!(is_data_directory_moved, true);
+        assert_eq!(context["region"].value, "us-central1");
+        assert_eq!(configs.len(), 2);
+        assert_eq!(configs.contains_key(&new_name), true);
+        assert_eq!(context_file.contains("[staging]"), false);
+        assert_eq!(context_file.contains("[production]"), true);
+    }
+
+    #[test]
+    fn test_update_context_section() {
+        let directory = create_test_directory("context-section");
+        let toml_path = directory.join("configs").join("context.toml");
+        fs::create_dir_all(directory.join("configs")).unwrap();
+        let content = r#"qa.region = "us-west-2"
+staging = { region = "us-east-1" }
+
+[dev]
+region = "eu-west-1" # primary
+
+[dev.meta]
+owner = "dev team"
+
+[devops]
+region = "eu-central-1"
+"#;
+        fs::write(&toml_path, content).unwrap();
+
+        let directory = directory.to_string_lossy().into_owned();
+        let (dev, staging, qa) = ("dev".to_string(), "staging".to_string(), "qa".to_string());
+        update_context_section(&directory, &dev, Some(&"development".to_string())).unwrap();
+        update_context_section(&directory, &staging, None).unwrap();
+        update_context_section(&directory, &qa, Some(&"eu/qa".to_string())).unwrap();
+        let updated_content = fs::read_to_string(&toml_path).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(
+            updated_content,
+            r#"eu.qa.region = "us-west-2"
+
+[development]
+region = "eu-west-1" # primary
+
+[development.meta]
+owner = "dev team"
+
+[devops]
+region = "eu-central-1"
+"#
+        );
+    }
+}
@@ -1,115 +1,677 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
-use std::io::Write;
+use std::io::ErrorKind;
 use std::path::Path;
 
 use handlebars::Handlebars;
 use quick_error::ResultExt;
-use serde_json::json;
+use serde_json::{json, Map as JsonMap, Value as JsonValue};
+use similar::TextDiff;
 use toml::Value as TomlValue;
 
 use crate::error::SageError;
-use crate::terminal::print_info;
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::metadata::METADATA_SECTION_NAME;
+use crate::outputs::{mask_stack_outputs, resolve_stack_outputs};
+use crate::terminal::{print_info, print_warning};
+use crate::utils::{
+    get_configs, get_content_hash, is_correct_config, parse_toml_file, write_file_atomically,
+    CONFIG_NAME_SEPARATOR,
+};
 
 pub const CONFIG_TEMPLATE_PARAM: &'static str = "CONFIG_NAME";
+pub const CONFIG_PATH_TEMPLATE_PARAM: &'static str = "CONFIG_PATH";
+pub const CONTEXT_FILE_PATH: &'static str = "configs/context.toml";
+pub const SECRETS_PARAM: &'static str = "secrets";
+pub const DEFAULTS_SECTION_NAME: &'static str = "defaults";
+pub const INTERPOLATION_START: &'static str = "{{";
+pub const INTERPOLATION_END: &'static str = "}}";
+pub const FROM_STACK_PARAM: &'static str = "from_stack";
+pub const OUTPUT_PARAM: &'static str = "output";
+pub const SECRET_VALUE_MASK: &'static str = "********";
+pub const BUILTIN_CONTEXT_SOURCE: &'static str = "built-in";
+pub const GENERATED_FILE_MARKER: &'static str = "# Generated by terraform-sage";
+pub const CONTENT_HASH_MARKER: &'static str = "# Content hash: sha256:";
+
+// A single value of the template context with the place where it was defined.
+// Values taken from the outputs of other stacks are resolved before rendering.
+#[derive(Debug, Clone)]
+pub struct ContextEntry {
+    pub value: JsonValue,
+    pub source: String,
+    pub secret: bool,
+    pub reference: Option<OutputReference>,
+}
+
+// Reference to the output of another stack, declared in the context file as
+// `key = { from_stack = "../network", output = "vpc_id" }`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputReference {
+    pub stack: String,
+    pub output: String,
+}
+
+// Resolved template context, ordered by the key names.
+pub type TemplateContext = BTreeMap<String, ContextEntry>;
 
 // Generates file name for Terraform main module.
 pub fn generate_file_name(target: &String) -> String {
-    format!("main-{}.tf", target).to_owned()
+    format!("main-{}.tf", target.replace(CONFIG_NAME_SEPARATOR, "-")).to_owned()
 }
 
 // Generates new Terraform module from the file with name specified
 // in `target` parameter and save the rendered content in file with
-// the name specified in `out` parameter.
+// the name specified in `out` parameter. Existing files that weren't
+// generated by terraform-sage are overwritten only with `force` flag.
 pub fn generate_from_template(
     directory: &String,
     config: &String,
     target: &String,
     out: &String,
+    force: bool,
+) -> Result<String, SageError> {
+    let mut manifest = Manifest::load_for(out);
+    generate_tracked_file(directory, config, target, out, force, &mut manifest)
+}
+
+// Generates new Terraform module the same way as `generate_from_template`,
+// but records the generated file in the given manifest instead of the one
+// located next to the file.
+pub fn generate_tracked_file(
+    directory: &String,
+    config: &String,
+    target: &String,
+    out: &String,
+    force: bool,
+    manifest: &mut Manifest,
+) -> Result<String, SageError> {
+    if !force && Path::new(out).exists() && !is_tracked_file(out, manifest) {
+        return Err(SageError::UnmanagedFile(out.clone()));
+    }
+
+    print_info("Generating Terraform file...");
+    let module = render_from_template(directory, config, target, true)?;
+
+    match write_file_atomically(out, &module)? {
+        true => print_info(&format!("New Terraform file was created by path: {}", out)),
+        false => print_info(&format!("Terraform file by path {} is up to date.", out)),
+    };
+    save_generated_file(out, target, config, &module, manifest)?;
+    Ok(out.to_string())
+}
+
+// Renders the template with `template` name, located in the directory
+// specified in `directory` parameter, for the configuration with `config`
+// name and returns the rendered Terraform module without writing anything
+// on the disk. The outputs of other stacks aren't read, so their references
+// are rendered as the descriptions of the outputs.
+pub fn render(directory: &String, config: &String, template: &String) -> Result<String, SageError> {
+    let configs = get_configs(directory)?;
+    is_correct_config(config, configs)?;
+    let path_to_target = Path::new(directory)
+        .join(template)
+        .to_string_lossy()
+        .into_owned();
+    render_from_template(directory, config, &path_to_target, false)
+}
+
+// Renders the template from the file with name specified in `target`
+// parameter for the configuration with `config` name and returns the
+// rendered content with the terraform-sage header. The references to the
+// outputs of other stacks are replaced by their values only with the
+// `resolve_outputs` flag.
+pub fn render_from_template(
+    directory: &String,
+    config: &String,
+    target: &String,
+    resolve_outputs: bool,
 ) -> Result<String, SageError> {
     let handlebars = Handlebars::new();
     let template = fs::read_to_string(target).context(target)?;
-    let mut context = get_template_context(directory, config);
-    context.insert(CONFIG_TEMPLATE_PARAM.to_string(), config.clone());
+    let mut context = get_template_context(directory, config)?;
+    match resolve_outputs {
+        true => resolve_stack_outputs(directory, config, &mut context)?,
+        false => mask_stack_outputs(&mut context),
+    }
 
-    print_info("Generating Terraform file...");
-    let template_parameters = json!(context);
+    let template_parameters = get_template_parameters(&context);
     let module = handlebars
         .render_template(&template, &template_parameters)
-        .context(out)?;
+        .context(target)?;
 
-    let mut file = fs::File::create(out).context(out)?;
-    file.write_all(module.as_bytes()).context(out)?;
-    print_info(&format!("New Terraform file was created by path: {}", out));
-    Ok(out.to_string())
+    let template_name = Path::new(target)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| target.clone());
+    let header = format!(
+        "{} from {} for {}, do not edit.\n{}{}\n",
+        GENERATED_FILE_MARKER,
+        template_name,
+        config,
+        CONTENT_HASH_MARKER,
+        get_content_hash(&module)
+    );
+    Ok(format!("{}{}", header, module))
+}
+
+// Checks that the file by the given path was generated by terraform-sage
+// and wasn't modified after it. The file is considered as generated when
+// it has the terraform-sage header with the correct content hash or it is
+// tracked by the manifest with the same hash.
+pub fn is_generated_file(path: &String) -> bool {
+    is_tracked_file(path, &Manifest::load_for(path))
+}
+
+// Checks that the file by the given path was generated by terraform-sage
+// the same way as `is_generated_file`, but looks it up in the given manifest.
+fn is_tracked_file(path: &String, manifest: &Manifest) -> bool {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(_) => return false,
+    };
+
+    let is_tracked = manifest
+        .get(path)
+        .map(|entry| entry.hash == get_content_hash(&content))
+        .unwrap_or(false);
+
+    is_tracked || has_valid_header(&content)
+}
+
+// Checks that the content starts with the terraform-sage header and the
+// hash in the header matches the rest of the content.
+fn has_valid_header(content: &str) -> bool {
+    let mut parts = content.splitn(3, '\n');
+    let marker = parts.next().unwrap_or_default();
+    let hash = parts.next().unwrap_or_default();
+    let module = parts.next().unwrap_or_default();
+
+    marker.starts_with(GENERATED_FILE_MARKER)
+        && hash.strip_prefix(CONTENT_HASH_MARKER) == Some(get_content_hash(module).as_str())
+}
+
+// Returns the reason why the generated file by path in `filepath` parameter
+// is out of date: the file was modified after generation or the template
+// was changed since. Returns `None` for up-to-date and unknown files.
+pub fn get_outdated_reason(filepath: &String) -> Option<String> {
+    let manifest = Manifest::load_for(filepath);
+    let entry = manifest.get(filepath)?;
+
+    let content = fs::read_to_string(filepath).ok()?;
+    if entry.hash != get_content_hash(&content) {
+        return Some(format!("{} was modified after generation", filepath));
+    }
+
+    let template = fs::read_to_string(&entry.template).unwrap_or_default();
+    if entry.template_hash != get_content_hash(&template) {
+        return Some(format!(
+            "{} was changed after {} generation",
+            entry.template, filepath
+        ));
+    }
+
+    None
+}
+
+// Records the generated file by path in `out` parameter in the manifest.
+fn save_generated_file(
+    out: &String,
+    target: &String,
+    config: &String,
+    module: &str,
+    manifest: &mut Manifest,
+) -> Result<(), SageError> {
+    let template = fs::read_to_string(target).context(target)?;
+    let template_path = fs::canonicalize(target)
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| target.clone());
+    let entry = ManifestEntry {
+        template: template_path,
+        config: config.clone(),
+        hash: get_content_hash(module),
+        template_hash: get_content_hash(&template),
+    };
+    manifest.insert(out, entry);
+    manifest.save()
 }
 
-// Parses the template context from the properties.toml file, located
+// Returns a unified diff between the current content of the file with
+// `filename` name and the newly rendered content.
+pub fn diff_rendered_file(filename: &String, current: &str, rendered: &str) -> String {
+    TextDiff::from_lines(current, rendered)
+        .unified_diff()
+        .header(filename, filename)
+        .to_string()
+}
+
+// Parses the template context from the context.toml file, located
 // in the given directory with the `config` name. If the file doesn't
-// exist or the context not found, then returns an empty context.
-pub fn get_template_context(directory: &String, config: &String) -> HashMap<String, String>{
-    let mut context = HashMap::new();
+// exist or the context not found, then returns a context that contains
+// only the built-in parameters. A malformed file is reported as error.
+// The `[defaults]` section is applied first. For nested configurations the
+// sections of the parent directories are applied next, so that the keys of
+// the innermost section take precedence. Placeholders of other keys in the
+// values are replaced at the end.
+pub fn get_template_context(
+    directory: &String,
+    config: &String,
+) -> Result<TemplateContext, SageError> {
+    let mut context = get_raw_template_context(directory, config)?;
+    interpolate_context(&mut context)?;
+    Ok(context)
+}
 
-    let toml_path: String = Path::new(directory)
-        .join("configs/context.toml")
-        .to_string_lossy()
-        .into_owned();
-    let raw_data = fs::read_to_string(&toml_path).unwrap_or("".to_string());
+// Returns the template context of the configuration with `config` name like
+// the `get_template_context` function, but with the placeholders of other keys
+// left in the values.
+pub fn get_raw_template_context(
+    directory: &String,
+    config: &String,
+) -> Result<TemplateContext, SageError> {
+    let mut context = TemplateContext::new();
 
-    match raw_data.parse::<TomlValue>() {
-        Ok(toml_root) => {
-            match toml_root.as_table() {
-                Some(table) => table
+    if let Some((toml_path, toml_root)) = read_context_file(directory)? {
+        let config_names: Vec<String> = get_configs(directory)
+            .map(|configs| configs.into_keys().collect())
+            .unwrap_or_default();
+        let mut sections = vec![];
+        if let Some(defaults) = toml_root.get(DEFAULTS_SECTION_NAME) {
+            sections.push((DEFAULTS_SECTION_NAME.to_string(), defaults, BTreeSet::new()));
+        }
+
+        let segments: Vec<&str> = config.split(CONFIG_NAME_SEPARATOR).collect();
+        for index in 1..=segments.len() {
+            let section_config = segments[..index].join(CONFIG_NAME_SEPARATOR);
+            if let Some(section) = get_context_section(&toml_root, &section_config) {
+                let prefix = format!("{}{}", section_config, CONFIG_NAME_SEPARATOR);
+                let mut nested_sections: BTreeSet<String> = config_names
                     .iter()
-                    .filter(|(key, _value)| key == &config)
-                    .for_each(|(_key, value)| {
-                        let variables = convert_toml_to_hashmap(value);
-                        context.extend(variables);
-                    }),
-                None => return context,
+                    .filter_map(|name| name.strip_prefix(&prefix))
+                    .filter_map(|name| name.split(CONFIG_NAME_SEPARATOR).next())
+                    .map(String::from)
+                    .collect();
+                nested_sections.insert(METADATA_SECTION_NAME.to_string());
+                let section_name = format_section_name(&section_config);
+                sections.push((section_name, section, nested_sections));
             }
-        },
-        Err(_) => return context,
+        }
+
+        for (section_name, section, nested_sections) in sections {
+            let source = format!("{} [{}]", toml_path, section_name);
+            for (key, mut entry) in convert_toml_to_context(section, &source, &nested_sections) {
+                entry.secret |= context.get(&key).is_some_and(|parent| parent.secret);
+                context.insert(key, entry);
+            }
+        }
     };
 
-    context
+    let config_path: Vec<JsonValue> = config
+        .split(CONFIG_NAME_SEPARATOR)
+        .map(|segment| JsonValue::String(segment.to_string()))
+        .collect();
+    context.insert(
+        CONFIG_TEMPLATE_PARAM.to_string(),
+        ContextEntry {
+            value: JsonValue::String(config.clone()),
+            source: BUILTIN_CONTEXT_SOURCE.to_string(),
+            secret: false,
+            reference: None,
+        },
+    );
+    context.insert(
+        CONFIG_PATH_TEMPLATE_PARAM.to_string(),
+        ContextEntry {
+            value: JsonValue::Array(config_path),
+            source: BUILTIN_CONTEXT_SOURCE.to_string(),
+            secret: false,
+            reference: None,
+        },
+    );
+    Ok(context)
 }
 
-// Converts the given sub-toml into the flat hashmap that stores key-value pairs
-// for the used environment. Supported only the strings as the values.
-fn convert_toml_to_hashmap(value: &TomlValue) -> HashMap<String, String> {
-    let mut context = HashMap::new();
+// Prints a warning, if the context file exists, but doesn't have the section
+// for the configuration with `config` name. Errors of reading the file are
+// left to the command, which uses the context.
+pub fn warn_missing_context_section(directory: &String, config: &String) {
+    let is_known_config = get_configs(directory)
+        .map(|configs| configs.contains_key(config))
+        .unwrap_or(false);
+    if let (true, Ok(Some((toml_path, toml_root)))) =
+        (is_known_config, read_context_file(directory))
+    {
+        if get_context_section(&toml_root, config).is_none() {
+            print_warning(&format!(
+                "The {} file doesn't have the [{}] section.",
+                toml_path,
+                format_section_name(config)
+            ));
+        }
+    }
+}
+
+// Returns the section of the context file for the configuration with `config`
+// name. Sections of the nested configurations are the nested tables, e.g.
+// `[eu-west-1.production]` for the `eu-west-1/production` configuration.
+pub fn get_context_section<'a>(toml_root: &'a TomlValue, config: &String) -> Option<&'a TomlValue> {
+    config
+        .split(CONFIG_NAME_SEPARATOR)
+        .try_fold(toml_root, |table, segment| table.get(segment))
+        .filter(|section| section.is_table())
+}
+
+// Returns the name of the context file section for the configuration with
+// `config` name, in the form used in the table headers.
+pub fn format_section_name(config: &String) -> String {
+    config
+        .split(CONFIG_NAME_SEPARATOR)
+        .map(|segment| format_toml_key(&segment.to_string()))
+        .collect::<Vec<String>>()
+        .join(".")
+}
+
+// Reads the context.toml file, located in the given directory, and returns
+// the path to the file with its parsed content. If the file doesn't exist,
+// then returns `None`.
+pub fn read_context_file(directory: &String) -> Result<Option<(String, TomlValue)>, SageError> {
+    let toml_path: String = Path::new(directory)
+        .join(CONTEXT_FILE_PATH)
+        .to_string_lossy()
+        .into_owned();
+
+    match fs::read_to_string(&toml_path) {
+        Ok(raw_data) => {
+            let toml_root = parse_context_file(&toml_path, &raw_data)?;
+            Ok(Some((toml_path, toml_root)))
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(SageError::Io(err, toml_path)),
+    }
+}
+
+// Parses the content of the context file. Parse errors are converted into
+// the error with the position of the invalid definition in the file.
+pub fn parse_context_file(toml_path: &String, raw_data: &str) -> Result<TomlValue, SageError> {
+    parse_toml_file(toml_path, raw_data, SageError::ContextParse)
+}
+
+// Converts the given sub-toml into the flat context that stores key-value pairs
+// for the used environment. Strings, numbers, booleans, dates, arrays and
+// tables are converted to the values of the template parameters, and tables
+// with the `from_stack` key are the references to the outputs of other stacks.
+// Keys in `sections` are the nested sections of the context file (metadata
+// and nested configurations), so they are not the values. Keys listed in the
+// `secrets` array are marked as secrets.
+fn convert_toml_to_context(
+    value: &TomlValue,
+    source: &String,
+    sections: &BTreeSet<String>,
+) -> TemplateContext {
+    let mut context = TemplateContext::new();
 
     if let Some(definitions) = value.as_table() {
+        let secrets: Vec<&str> = definitions
+            .get(SECRETS_PARAM)
+            .and_then(|value| value.as_array())
+            .map(|keys| keys.iter().filter_map(|key| key.as_str()).collect())
+            .unwrap_or_default();
+
         definitions
             .iter()
+            .filter(|(key, _toml_value)| key.as_str() != SECRETS_PARAM && !sections.contains(*key))
             .for_each(|(key, toml_value)| {
-                match toml_value {
-                    TomlValue::String(value) => {
-                        context.insert(key.clone(), value.clone());
+                let secret = secrets.contains(&key.as_str());
+                let stack = toml_value.get(FROM_STACK_PARAM).and_then(|v| v.as_str());
+                let entry = match (toml_value, stack) {
+                    (TomlValue::Table(table), Some(stack)) => {
+                        let output = table
+                            .get(OUTPUT_PARAM)
+                            .and_then(|value| value.as_str())
+                            .unwrap_or(key);
+                        ContextEntry {
+                            value: JsonValue::Null,
+                            source: format!("{} (output {} of {})", source, output, stack),
+                            secret,
+                            reference: Some(OutputReference {
+                                stack: stack.to_string(),
+                                output: output.to_string(),
+                            }),
+                        }
+                    }
+                    _ => ContextEntry {
+                        value: convert_toml_to_json(toml_value),
+                        source: source.clone(),
+                        secret,
+                        reference: None,
                     },
-                    _ => {},
                 };
+                context.insert(key.clone(), entry);
             })
     };
 
     context
 }
 
+// Converts the TOML value to the JSON value, passed to the template engine.
+// Dates are converted to strings in the TOML format.
+fn convert_toml_to_json(value: &TomlValue) -> JsonValue {
+    match value {
+        TomlValue::String(value) => JsonValue::String(value.clone()),
+        TomlValue::Integer(value) => JsonValue::from(*value),
+        TomlValue::Float(value) => JsonValue::from(*value),
+        TomlValue::Boolean(value) => JsonValue::Bool(*value),
+        TomlValue::Datetime(value) => JsonValue::String(value.to_string()),
+        TomlValue::Array(values) => values.iter().map(convert_toml_to_json).collect(),
+        TomlValue::Table(table) => JsonValue::Object(
+            table
+                .iter()
+                .map(|(key, value)| (key.clone(), convert_toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+// Replaces the placeholders of other keys in the string values of the context,
+// e.g. `bucket = "{{CONFIG_NAME}}-data"`, by the values of these keys. Values,
+// which include the secrets, become secrets too. References to unknown keys,
+// to the outputs of other stacks and the circular references are reported as
+// errors.
+fn interpolate_context(context: &mut TemplateContext) -> Result<(), SageError> {
+    let keys: Vec<String> = context.keys().cloned().collect();
+    for key in keys.iter() {
+        let (value, secret) = interpolate_entry(context, key, &mut vec![])?;
+        if let Some(entry) = context.get_mut(key) {
+            entry.value = value;
+            entry.secret |= secret;
+        }
+    }
+    Ok(())
+}
+
+// Returns the value of the context entry with `key` name with the resolved
+// placeholders and whether it includes the secrets. The `chain` contains the
+// keys, which are being resolved, to detect the circular references.
+fn interpolate_entry(
+    context: &TemplateContext,
+    key: &String,
+    chain: &mut Vec<String>,
+) -> Result<(JsonValue, bool), SageError> {
+    let entry = &context[key];
+    let template = match &entry.value {
+        JsonValue::String(value) if value.contains(INTERPOLATION_START) => value,
+        value => return Ok((value.clone(), entry.secret)),
+    };
+    if chain.contains(key) {
+        chain.push(key.clone());
+        let message = format!(
+            "The context values refer to each other: {}.",
+            chain.join(" -> ")
+        );
+        return Err(SageError::InvalidConfig(message));
+    }
+
+    chain.push(key.clone());
+    let mut result = String::new();
+    let mut secret = entry.secret;
+    let mut rest = template.as_str();
+    while let Some((start, placeholder_end, name)) = find_placeholder(rest) {
+        if !is_key_name(name) {
+            result.push_str(&rest[..placeholder_end]);
+            rest = &rest[placeholder_end..];
+            continue;
+        }
+
+        let referenced_entry = context.get(name).ok_or_else(|| {
+            let message = format!(
+                "The `{}` context value refers to the unknown `{}` key ({}).",
+                key, name, entry.source
+            );
+            SageError::InvalidConfig(message)
+        })?;
+        if referenced_entry.reference.is_some() {
+            let message = format!(
+                "The `{}` context value refers to the `{}` stack output, which can't be interpolated ({}).",
+                key, name, entry.source
+            );
+            return Err(SageError::InvalidConfig(message));
+        }
+
+        let (value, referenced_secret) = interpolate_entry(context, &name.to_string(), chain)?;
+        let value = match value {
+            JsonValue::String(value) => value,
+            JsonValue::Number(_) | JsonValue::Bool(_) => value.to_string(),
+            _ => {
+                let message = format!(
+                    "The `{}` context value refers to the `{}` key, which isn't a string, number or boolean ({}).",
+                    key, name, entry.source
+                );
+                return Err(SageError::InvalidConfig(message));
+            }
+        };
+        result.push_str(&rest[..start]);
+        result.push_str(&value);
+        secret |= referenced_secret;
+        rest = &rest[placeholder_end..];
+    }
+    result.push_str(rest);
+    chain.pop();
+    Ok((JsonValue::String(result), secret))
+}
+
+// Returns the keys, which placeholders are used in the string value of the
+// context, e.g. `region` for `bucket = "{{region}}-data"`.
+pub fn get_interpolated_keys(value: &JsonValue) -> Vec<String> {
+    let mut keys = Vec::new();
+    let mut rest = value.as_str().unwrap_or_default();
+    while let Some((_start, placeholder_end, name)) = find_placeholder(rest) {
+        if is_key_name(name) {
+            keys.push(name.to_string());
+        }
+        rest = &rest[placeholder_end..];
+    }
+    keys
+}
+
+// Finds the first placeholder in the string and returns its start and end
+// positions with the trimmed content between the delimiters.
+fn find_placeholder(value: &str) -> Option<(usize, usize, &str)> {
+    let start = value.find(INTERPOLATION_START)?;
+    let placeholder = &value[start + INTERPOLATION_START.len()..];
+    let end = placeholder.find(INTERPOLATION_END)?;
+    let placeholder_end = start + INTERPOLATION_START.len() + end + INTERPOLATION_END.len();
+    Some((start, placeholder_end, placeholder[..end].trim()))
+}
+
+// Checks that the content of the placeholder is the name of the context key,
+// rather than a helper call or an expression.
+fn is_key_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+// Returns parameters for the template engine from the given context.
+pub fn get_template_parameters(context: &TemplateContext) -> JsonValue {
+    let parameters: JsonMap<String, JsonValue> = context
+        .iter()
+        .map(|(key, entry)| (key.clone(), entry.value.clone()))
+        .collect();
+    JsonValue::Object(parameters)
+}
+
+// Formats the resolved template context as TOML or JSON document. When the
+// `show_source` flag is set, each key is annotated with the place where it
+// was defined. Values of the secret keys are always masked.
+pub fn format_context(context: &TemplateContext, format: &str, show_source: bool) -> String {
+    let get_value = |entry: &ContextEntry| match entry.secret {
+        true => JsonValue::String(SECRET_VALUE_MASK.to_string()),
+        false => entry.value.clone(),
+    };
+
+    match format {
+        "json" => {
+            let document: JsonMap<String, JsonValue> = context
+                .iter()
+                .map(|(key, entry)| {
+                    let value = match show_source {
+                        true => json!({"value": get_value(entry), "source": entry.source}),
+                        false => get_value(entry),
+                    };
+                    (key.clone(), value)
+                })
+                .collect();
+            serde_json::to_string_pretty(&document).unwrap_or_default()
+        }
+        _ => context
+            .iter()
+            .map(|(key, entry)| {
+                let value = TomlValue::try_from(get_value(entry))
+                    .map(|value| value.to_string())
+                    .unwrap_or_default();
+                match show_source {
+                    true => format!("{} = {}  # {}", format_toml_key(key), value, entry.source),
+                    false => format!("{} = {}", format_toml_key(key), value),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+    }
+}
+
+// Returns the key in the form acceptable for TOML documents.
+pub fn format_toml_key(key: &String) -> String {
+    let is_bare_key = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    match is_bare_key {
+        true => key.clone(),
+        false => TomlValue::String(key.clone()).to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
-    use std::env;
     use std::path::Path;
 
-    use crate::template::generate_from_template;
+    use serde_json::json;
+
+    use crate::error::SageError;
+    use crate::template::{
+        diff_rendered_file, format_context, generate_from_template, get_template_context,
+        is_generated_file, render, render_from_template,
+    };
+    use crate::utils::create_test_directory;
 
     #[test]
     fn test_generate_from_template() {
         let directory = "./examples/approach_two".to_string();
         let config = String::from("dev");
         let used_directory = Path::new(directory.as_str());
-        let out_directory = env::temp_dir();
+        let out_directory = create_test_directory("generate");
         let path_to_target = used_directory
             .join("main.tpl")
             .to_string_lossy()
@@ -118,10 +680,14 @@ mod tests {
             .join("main.tf")
             .to_string_lossy()
             .into_owned();
-        let result = generate_from_template(&directory, &config, &path_to_target, &path_to_out);
+        let result =
+            generate_from_template(&directory, &config, &path_to_target, &path_to_out, false);
+
+        let is_manifest_saved = out_directory.join(".sage/manifest.json").exists();
+        fs::remove_dir_all(&out_directory).unwrap();
 
         assert_eq!(result.is_ok(), true);
-        fs::remove_file(path_to_out).unwrap();
+        assert_eq!(is_manifest_saved, true);
     }
 
     #[test]
@@ -129,7 +695,7 @@ mod tests {
         let directory = "./examples/INVALID_PATH".to_string();
         let config = String::from("dev");
         let used_directory = Path::new(directory.as_str());
-        let out_directory = env::temp_dir();
+        let out_directory = create_test_directory("generate-invalid-path");
         let path_to_target = used_directory
             .join("main.tpl")
             .to_string_lossy()
@@ -138,7 +704,10 @@ mod tests {
             .join("main.tf")
             .to_string_lossy()
             .into_owned();
-        let result = generate_from_template(&directory, &config, &path_to_target, &path_to_out);
+        let result =
+            generate_from_template(&directory, &config, &path_to_target, &path_to_out, false);
+
+        fs::remove_dir_all(&out_directory).unwrap();
 
         assert_eq!(result.is_err(), true);
     }
@@ -148,7 +717,7 @@ mod tests {
         let directory = "./example/examples".to_string();
         let config = String::from("dev");
         let used_directory = Path::new(directory.as_str());
-        let out_directory = env::temp_dir();
+        let out_directory = create_test_directory("generate-invalid-target");
         let path_to_target = used_directory
             .join("INVALID_FILE_NAME")
             .to_string_lossy()
@@ -157,7 +726,250 @@ mod tests {
             .join("main.tf")
             .to_string_lossy()
             .into_owned();
-        let result = generate_from_template(&directory, &config, &path_to_target, &path_to_out);
+        let result =
+            generate_from_template(&directory, &config, &path_to_target, &path_to_out, false);
+
+        fs::remove_dir_all(&out_directory).unwrap();
+
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn test_get_template_context_returns_section_with_config_name() {
+        let directory = "./examples/context_switch".to_string();
+        let config = String::from("dev");
+        let context = get_template_context(&directory, &config).unwrap();
+
+        assert_eq!(context.len(), 4);
+        assert_eq!(context["CONFIG_NAME"].value, "dev");
+        assert_eq!(context["aws_bucket_name"].value, "dev-bucket");
+        assert_eq!(context["CONFIG_NAME"].source, "built-in");
+        assert_eq!(
+            context["profile"].source,
+            "./examples/context_switch/configs/context.toml [dev]"
+        );
+    }
+
+    #[test]
+    fn test_format_context_masks_secrets() {
+        let directory = "./examples/context_switch".to_string();
+        let config = String::from("dev");
+        let mut context = get_template_context(&directory, &config).unwrap();
+        context.get_mut("profile").unwrap().secret = true;
+
+        let output = format_context(&context, "toml", false);
+        assert_eq!(
+            output,
+            "CONFIG_NAME = \"dev\"\nCONFIG_PATH = [\"dev\"]\naws_bucket_name = \"dev-bucket\"\nprofile = \"********\""
+        );
+
+        let output = format_context(&context, "json", true);
+        assert_eq!(output.contains("\"source\": \"built-in\""), true);
+        assert_eq!(output.contains("dev-profile"), false);
+    }
+
+    #[test]
+    fn test_get_template_context_returns_error_for_malformed_file() {
+        let directory = create_test_directory("malformed-context");
+        fs::create_dir_all(directory.join("configs")).unwrap();
+        fs::write(
+            directory.join("configs/context.toml"),
+            "[dev]\nprofile = \"dev-profile\nregion = \"us-east-1\"\n",
+        )
+        .unwrap();
+
+        let directory = directory.to_string_lossy().into_owned();
+        let result = get_template_context(&directory, &"dev".to_string());
+        fs::remove_dir_all(&directory).unwrap();
+
+        match result {
+            Err(SageError::ContextParse(_, line, column, message)) => {
+                assert_eq!(line, 2);
+                assert_eq!(column, 23);
+                assert_eq!(message, "newline in string found");
+            }
+            _ => panic!("expected the context parse error"),
+        }
+    }
+
+    #[test]
+    fn test_get_template_context_allows_missing_file() {
+        let directory = "./examples/approach_two".to_string();
+        let context = get_template_context(&directory, &"dev".to_string()).unwrap();
+
+        assert_eq!(context.len(), 2);
+        assert_eq!(context.contains_key("CONFIG_NAME"), true);
+    }
+
+    #[test]
+    fn test_get_template_context_with_stack_outputs() {
+        let directory = create_test_directory("stack-outputs");
+        fs::create_dir_all(directory.join("configs/dev")).unwrap();
+        fs::write(
+            directory.join("configs/context.toml"),
+            "[dev]\nvpc_id = { from_stack = \"../network\", output = \"main_vpc_id\" }\nsubnets = { from_stack = \"../network\" }\n",
+        )
+        .unwrap();
+
+        let directory = directory.to_string_lossy().into_owned();
+        let context = get_template_context(&directory, &"dev".to_string()).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let reference = context["vpc_id"].reference.clone().unwrap();
+        assert_eq!(reference.stack, "../network");
+        assert_eq!(reference.output, "main_vpc_id");
+        assert_eq!(
+            context["subnets"].reference.clone().unwrap().output,
+            "subnets"
+        );
+    }
+
+    #[test]
+    fn test_get_template_context_for_nested_configuration() {
+        let directory = create_test_directory("nested-context");
+        fs::create_dir_all(directory.join("configs/eu-west-1/prod")).unwrap();
+        fs::write(
+            directory.join("configs/context.toml"),
+            "[eu-west-1]\nregion = \"eu-west-1\"\nprofile = \"eu\"\n\n[eu-west-1.prod]\nprofile = \"eu-prod\"\n",
+        )
+        .unwrap();
+
+        let directory = directory.to_string_lossy().into_owned();
+        let context = get_template_context(&directory, &"eu-west-1/prod".to_string()).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(context["region"].value, "eu-west-1");
+        assert_eq!(context["profile"].value, "eu-prod");
+        assert_eq!(context["CONFIG_NAME"].value, "eu-west-1/prod");
+        assert_eq!(context["CONFIG_PATH"].value, json!(["eu-west-1", "prod"]));
+    }
+
+    #[test]
+    fn test_get_template_context_with_defaults_and_interpolation() {
+        let directory = create_test_directory("interpolated-context");
+        fs::create_dir_all(directory.join("configs/dev")).unwrap();
+        fs::write(
+            directory.join("configs/context.toml"),
+            [
+                "[defaults]",
+                "region = \"us-east-1\"",
+                "replicas = 1",
+                "bucket = \"{{CONFIG_NAME}}-{{ region }}-data\"",
+                "",
+                "[dev]",
+                "replicas = 2",
+                "enabled = true",
+                "zones = [\"a\", \"b\"]",
+                "tags = { team = \"infra\" }",
+                "token = \"qwerty\"",
+                "url = \"https://{{token}}@example.com/{{replicas}}\"",
+                "secrets = [\"token\"]",
+                "",
+                "[dev.meta]",
+                "owner = \"infra\"",
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+
+        let directory = directory.to_string_lossy().into_owned();
+        let context = get_template_context(&directory, &"dev".to_string()).unwrap();
+        fs::write(
+            format!("{}/configs/context.toml", directory),
+            "[dev]\na = \"{{b}}\"\nb = \"{{a}}\"\nc = \"{{unknown}}\"\n",
+        )
+        .unwrap();
+        let result = get_template_context(&directory, &"dev".to_string());
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(context["region"].value, "us-east-1");
+        assert_eq!(context["replicas"].value, json!(2));
+        assert_eq!(context["bucket"].value, "dev-us-east-1-data");
+        assert_eq!(context["enabled"].value, json!(true));
+        assert_eq!(context["zones"].value, json!(["a", "b"]));
+        assert_eq!(context["tags"].value, json!({"team": "infra"}));
+        assert_eq!(context["url"].value, "https://qwerty@example.com/2");
+        assert_eq!(context["url"].secret, true);
+        assert_eq!(context.contains_key("secrets"), false);
+        assert_eq!(context.contains_key("meta"), false);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Invalid configuration: The context values refer to each other: a -> b -> a."
+        );
+    }
+
+    #[test]
+    fn test_generate_from_template_refuses_to_overwrite_foreign_file() {
+        let directory = "./examples/approach_two".to_string();
+        let config = String::from("dev");
+        let path_to_target = "./examples/approach_two/main.tpl".to_string();
+        let out_directory = create_test_directory("handwritten");
+        let path_to_out = out_directory.join("main.tf").to_string_lossy().into_owned();
+        fs::write(&path_to_out, "# hand-written module").unwrap();
+        assert_eq!(is_generated_file(&path_to_out), false);
+
+        let result =
+            generate_from_template(&directory, &config, &path_to_target, &path_to_out, false);
+        assert_eq!(result.is_err(), true);
+        assert_eq!(
+            fs::read_to_string(&path_to_out).unwrap(),
+            "# hand-written module"
+        );
+
+        let result =
+            generate_from_template(&directory, &config, &path_to_target, &path_to_out, true);
+        assert_eq!(result.is_ok(), true);
+        assert_eq!(is_generated_file(&path_to_out), true);
+        fs::remove_dir_all(out_directory).unwrap();
+    }
+
+    #[test]
+    fn test_is_generated_file_detects_modified_file() {
+        let directory = "./examples/approach_two".to_string();
+        let config = String::from("dev");
+        let path_to_target = "./examples/approach_two/main.tpl".to_string();
+        let module = render_from_template(&directory, &config, &path_to_target, true).unwrap();
+        let header = "# Generated by terraform-sage from main.tpl for dev, do not edit.\n";
+        assert_eq!(module.starts_with(header), true);
+
+        let out_directory = create_test_directory("modified");
+        let path_to_out = out_directory.join("main.tf").to_string_lossy().into_owned();
+        fs::write(&path_to_out, &module).unwrap();
+        assert_eq!(is_generated_file(&path_to_out), true);
+
+        fs::write(&path_to_out, module.replace("dev", "production")).unwrap();
+        assert_eq!(is_generated_file(&path_to_out), false);
+        fs::remove_dir_all(out_directory).unwrap();
+    }
+
+    #[test]
+    fn test_diff_rendered_file() {
+        let filename = "main.tf".to_string();
+        let diff = diff_rendered_file(&filename, "a = 1\nb = 2\n", "a = 1\nb = 3\n");
+
+        assert_eq!(
+            diff,
+            "--- main.tf\n+++ main.tf\n@@ -1,2 +1,2 @@\n a = 1\n-b = 2\n+b = 3\n"
+        );
+    }
+
+    #[test]
+    fn test_render_does_not_create_files() {
+        let directory = "./examples/context_switch".to_string();
+        let config = String::from("staging");
+        let template = String::from("main.tpl");
+        let module = render(&directory, &config, &template).unwrap();
+
+        assert_eq!(module.contains("profile = \"staging-profile\""), true);
+        assert_eq!(module.contains("./terraform-staging.tfstate"), true);
+        let path_to_out = Path::new("./examples/context_switch/main.tf");
+        assert_eq!(path_to_out.exists(), false);
+    }
+
+    #[test]
+    fn test_render_returns_error_for_unknown_config() {
+        let directory = "./examples/context_switch".to_string();
+        let result = render(&directory, &"qa".to_string(), &"main.tpl".to_string());
 
         assert_eq!(result.is_err(), true);
     }
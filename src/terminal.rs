@@ -1,23 +1,170 @@
-use ansi_term::Colour::{Green, Red, Yellow};
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use ansi_term::Colour::{Green, Purple, Red, Yellow};
+use ansi_term::Style;
 
 use crate::error::SageError;
+use crate::plan::{format_plan_totals, ChangeAction, PlanSummary, ResourceChange};
+use crate::policy::PolicyViolation;
+
+// Whether the messages are printed to stderr instead of stdout.
+static MESSAGES_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+// Prints all further messages to stderr, so that the stdout stays clean for
+// the commands that print machine-readable data.
+pub fn print_messages_to_stderr() {
+    MESSAGES_TO_STDERR.store(true, Ordering::SeqCst)
+}
+
+fn print_message(message: String) {
+    match MESSAGES_TO_STDERR.load(Ordering::SeqCst) {
+        true => eprintln!("{}", message),
+        false => println!("{}", message),
+    }
+}
 
 // Prints message that the job's done.
 pub fn print_command_done() {
-    println!("[{}] Done.", Green.paint("OK"))
+    print_message(format!("[{}] Done.", Green.paint("OK")))
 }
 
 // Prints regular message in terminal.
 pub fn print_info(message: &str) {
-    println!("[{}] {}", Green.paint("INFO"), message)
+    print_message(format!("[{}] {}", Green.paint("INFO"), message))
 }
 
 // Prints warning message in terminal.
 pub fn print_warning(message: &str) {
-    println!("[{}] {}", Yellow.paint("WARNING"), message)
+    print_message(format!("[{}] {}", Yellow.paint("WARNING"), message))
 }
 
 // Prints error message in terminal.
 pub fn print_error(err: SageError) {
-    println!("[{}] {}", Red.paint("ERROR"), err)
+    print_message(format!("[{}] {}", Red.paint("ERROR"), err))
+}
+
+// Asks the user to confirm the action and returns the typed answer.
+pub fn ask_confirmation(message: &str) -> String {
+    let prompt = format!("{} ", Style::new().bold().paint(message));
+    match MESSAGES_TO_STDERR.load(Ordering::SeqCst) {
+        true => {
+            eprint!("{}", prompt);
+            io::stderr().flush().unwrap_or_default()
+        }
+        false => {
+            print!("{}", prompt);
+            io::stdout().flush().unwrap_or_default()
+        }
+    }
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .unwrap_or_default();
+    answer.trim().to_string()
+}
+
+// Prints the table with the given header and rows in terminal. Columns are
+// aligned by the longest value.
+pub fn print_table(header: &[&str], rows: &Vec<Vec<String>>) {
+    let mut widths: Vec<usize> = header.iter().map(|title| title.chars().count()).collect();
+    rows.iter().for_each(|row| {
+        row.iter().enumerate().for_each(|(index, value)| {
+            if index < widths.len() {
+                widths[index] = widths[index].max(value.chars().count());
+            }
+        })
+    });
+
+    let format_row = |row: Vec<&str>| -> String {
+        row.iter()
+            .zip(widths.iter())
+            .map(|(value, width)| format!("{:width$}", value, width = width))
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    println!("{}", Style::new().bold().paint(format_row(header.to_vec())));
+    rows.iter()
+        .for_each(|row| println!("{}", format_row(row.iter().map(|v| v.as_str()).collect())));
+}
+
+// Prints the summary of the planned changes, grouped by the action and the
+// resource type, with the total number of changes for each action.
+pub fn print_plan_summary(summary: &PlanSummary) {
+    if summary.is_empty() {
+        println!("{}", Style::new().bold().paint("No changes."));
+        return;
+    }
+
+    summary.iter().for_each(|(action, resource_types)| {
+        let count: usize = resource_types
+            .values()
+            .map(|addresses| addresses.len())
+            .sum();
+        let (symbol, colour) = match action {
+            ChangeAction::Create => ("+", Green),
+            ChangeAction::Update => ("~", Yellow),
+            ChangeAction::Replace => ("-/+", Purple),
+            ChangeAction::Delete => ("-", Red),
+        };
+        println!(
+            "{}",
+            colour
+                .bold()
+                .paint(format!("{} {} ({})", symbol, action, count))
+        );
+        resource_types
+            .iter()
+            .for_each(|(resource_type, addresses)| {
+                println!("    {}: {}", resource_type, addresses.len())
+            });
+    });
+
+    println!(
+        "{}",
+        Style::new()
+            .bold()
+            .paint(format!("Plan: {}.", format_plan_totals(summary)))
+    );
+}
+
+// Prints the planned changes, forbidden by the policy rules.
+pub fn print_policy_violations(violations: &Vec<PolicyViolation>) {
+    let rows: Vec<Vec<String>> = violations
+        .iter()
+        .map(|violation| {
+            vec![
+                violation.rule.clone(),
+                violation.address.clone(),
+                violation.action.to_string(),
+                violation.message.clone(),
+            ]
+        })
+        .collect();
+    print_table(&["RULE", "RESOURCE", "ACTION", "MESSAGE"], &rows);
+}
+
+// Prints the resources, which will be deleted or replaced by the plan.
+pub fn print_destructive_changes(changes: &Vec<ResourceChange>) {
+    if changes.is_empty() {
+        return;
+    }
+
+    eprintln!(
+        "{}",
+        Red.bold().paint(format!(
+            "The plan deletes or replaces {} resource(s):",
+            changes.len()
+        ))
+    );
+    changes.iter().for_each(|change| {
+        eprintln!(
+            "    {}",
+            Red.paint(format!("{} ({})", change.address, change.action))
+        )
+    });
 }
@@ -1,16 +1,75 @@
+use std::env;
+use std::ffi::OsStr;
 use std::fs;
-use std::process::{Command, Stdio};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::Instant;
 
 use quick_error::ResultExt;
 
+use crate::audit::record_invocation;
 use crate::error::SageError;
+use crate::manifest::Manifest;
+use crate::project::ProjectConfig;
+use crate::template::is_generated_file;
 use crate::terminal::{print_info, print_warning};
-use crate::utils::{get_extension_from_filename, get_files_list};
+use crate::utils::{
+    get_data_directory, get_extension_from_filename, get_files_list, CONFIG_DIRECTORY_NAME,
+};
 
 pub const TERRAFORM_EXTENSIONS: &'static [&str; 2] = &["tf", "tfvars"];
+pub const DATA_DIRECTORY_VARIABLE: &'static str = "TF_DATA_DIR";
+pub const LOCK_FILE_NAME: &'static str = ".terraform.lock.hcl";
+pub const PLAN_OUT_ARG: &'static str = "-out=";
+pub const AUTO_APPROVE_ARG: &'static str = "-auto-approve";
+pub const DESTROY_ARG: &'static str = "-destroy";
+pub const DETAILED_EXITCODE_ARG: &'static str = "-detailed-exitcode";
+pub const REFRESH_ONLY_ARG: &'static str = "-refresh-only";
+pub const REFRESH_ONLY_VERSION: (u32, u32, u32) = (0, 15, 4);
+pub const NO_LOCK_ARG: &'static str = "-lock=false";
+pub const NO_INPUT_ARG: &'static str = "-input=false";
+pub const NO_COLOR_ARG: &'static str = "-no-color";
+pub const VALIDATE_REPORT_NAME: &'static str = "validate";
 
+#[derive(Default)]
 pub struct TerraformClient;
 
+// Describes what Terraform's command is executed for: the configuration with
+// `config` name of the project in the `directory` and the main module, which
+// was generated for it, if any. When the `data_directory` is set, then it is
+// passed to Terraform as the directory for its working data.
+#[derive(Debug, Clone)]
+pub struct CommandContext {
+    pub directory: String,
+    pub config: String,
+    pub module: Option<String>,
+    pub data_directory: Option<String>,
+}
+
+impl CommandContext {
+    // Initialize a new context of Terraform's command. The configuration gets
+    // its own data directory, when it is enabled in the project settings and
+    // the user hasn't set the `TF_DATA_DIR` environment variable.
+    pub fn new(
+        directory: &String,
+        config: &String,
+        module: Option<&String>,
+    ) -> Result<CommandContext, SageError> {
+        let project = ProjectConfig::load(directory)?;
+        let is_data_directory_used = project.terraform.data_per_environment
+            && env::var_os(DATA_DIRECTORY_VARIABLE).is_none();
+        Ok(CommandContext {
+            directory: directory.clone(),
+            config: config.clone(),
+            module: module.cloned(),
+            data_directory: match is_data_directory_used {
+                true => Some(get_data_directory(directory, config)),
+                false => None,
+            },
+        })
+    }
+}
+
 impl TerraformClient {
     // Initialize a new instance of Terraform client.
     pub fn new() -> TerraformClient {
@@ -26,9 +85,32 @@ impl TerraformClient {
     }
 
     // Returns list of Terraform variable modules found in the specified directory.
-    fn get_variable_modules(&self, directory: &String, args: &Vec<String>) -> Vec<String> {
-        get_files_list(directory)
-            .unwrap_or(vec![])
+    // Terraform is executed in the project directory, so that the paths are
+    // relative to the `project_directory`.
+    fn get_variable_modules(
+        &self,
+        directory: &String,
+        project_directory: &String,
+        args: &Vec<String>,
+    ) -> Vec<String> {
+        self.get_config_variable_files(directory)
+            .into_iter()
+            .map(
+                |path| match Path::new(&path).strip_prefix(project_directory) {
+                    Ok(relative_path) => relative_path.to_string_lossy().into_owned(),
+                    Err(_) => path,
+                },
+            )
+            .map(|path| format!("-var-file={}", path))
+            .filter(|arg| !args.contains(arg))
+            .collect()
+    }
+
+    // Returns sorted list of paths to Terraform variable files found in the
+    // specified directory.
+    pub fn get_variable_files(&self, directory: &String) -> Vec<String> {
+        let mut files: Vec<String> = get_files_list(directory)
+            .unwrap_or_default()
             .into_iter()
             .filter(|f| match f.metadata() {
                 Ok(metadata) => metadata.is_file(),
@@ -38,22 +120,48 @@ impl TerraformClient {
                 let file_name = f.file_name().to_string_lossy().into_owned();
                 !file_name.starts_with("out")
             })
-            .filter(|f| {
-                let path = f.path().to_string_lossy().into_owned();
-                let file_extension = get_extension_from_filename(&path);
-                TERRAFORM_EXTENSIONS
-                    .iter()
-                    .find(|&&ext| ext == file_extension)
-                    .is_some()
-            })
-            .map(|f| {
-                let path = f.path().to_string_lossy().into_owned();
-                String::from(&format!("-var-file={}", path))
+            .map(|f| f.path().to_string_lossy().into_owned())
+            .filter(|path| {
+                let file_extension = get_extension_from_filename(path);
+                TERRAFORM_EXTENSIONS.contains(&file_extension)
             })
-            .filter(|arg| !args.clone().contains(arg))
+            .collect();
+        files.sort();
+        files
+    }
+
+    // Returns list of paths to Terraform variable files used by the configuration
+    // located in the specified directory. For nested configurations the files
+    // from the parent directories inside of the `configs` directory go first,
+    // so that the values from the innermost directory take precedence.
+    pub fn get_config_variable_files(&self, config_directory: &String) -> Vec<String> {
+        let ancestors: Vec<&Path> = Path::new(config_directory).ancestors().collect();
+        let directories = match ancestors
+            .iter()
+            .position(|path| path.file_name() == Some(OsStr::new(CONFIG_DIRECTORY_NAME)))
+        {
+            Some(index) => ancestors[..index].to_vec(),
+            None => vec![Path::new(config_directory)],
+        };
+
+        directories
+            .iter()
+            .rev()
+            .flat_map(|path| self.get_variable_files(&path.to_string_lossy().into_owned()))
             .collect()
     }
 
+    // Returns the version of the installed Terraform, or None when it can't be
+    // determined.
+    pub fn get_version(&self) -> Option<(u32, u32, u32)> {
+        let output = Command::new("terraform")
+            .arg("version")
+            .stdin(Stdio::null())
+            .output()
+            .ok()?;
+        parse_version(&String::from_utf8_lossy(&output.stdout))
+    }
+
     // Prepares list of arguments, required for Terraform's plan/apply/destroy commands.
     pub fn get_command_args(
         &self,
@@ -62,68 +170,275 @@ impl TerraformClient {
         extra: &Vec<String>,
     ) -> Vec<String> {
         let mut terraform_args = self.extract_arguments(extra);
-        let mut variable_modules = self.get_variable_modules(config_directory, &terraform_args);
+        let mut variable_modules =
+            self.get_variable_modules(config_directory, directory, &terraform_args);
         terraform_args.append(&mut variable_modules);
-        terraform_args.push(directory.to_string());
         terraform_args
     }
 
     // Prepares list of arguments, required for Terraform's init command.
-    pub fn get_init_args(&self, directory: &String, extra: &Vec<String>) -> Vec<String> {
-        let mut terraform_args = self.extract_arguments(extra);
-        terraform_args.push(directory.to_string());
+    pub fn get_init_args(&self, extra: &Vec<String>) -> Vec<String> {
+        self.extract_arguments(extra)
+    }
+
+    // Prepares list of arguments, required for Terraform's validate command.
+    // The command is called in the directory with Terraform files, and its
+    // output is not coloured, so that the errors can be saved in reports.
+    pub fn get_validate_args(&self, extra: &Vec<String>) -> Vec<String> {
+        let mut terraform_args = vec![NO_COLOR_ARG.to_string()];
+        terraform_args.append(&mut self.extract_arguments(extra));
         terraform_args
     }
 
+    // Prepares Terraform's process for the command with the given `command` name
+    // and `args` arguments. Every command is executed in the project directory
+    // from the `context`, so that Terraform finds the same modules, `.terraform`
+    // directory and local state for each of them. The working data of Terraform
+    // is stored in the data directory from the `context`, if it is set.
+    fn get_process(&self, command: &str, args: &Vec<String>, context: &CommandContext) -> Command {
+        let mut process = Command::new("terraform");
+        process
+            .arg(command)
+            .args(args)
+            .current_dir(&context.directory);
+        if let Some(data_directory) = &context.data_directory {
+            process.env(DATA_DIRECTORY_VARIABLE, data_directory);
+        }
+        process
+    }
+
     // Invokes Terraform's command with the given `command` name and `args` arguments.
     // The output of this command is printing in user's terminal. In the case of any errors
     // also prints captured errors.
-    pub fn call_without_input(&self, command: &str, args: &Vec<String>) -> Result<(), SageError> {
+    pub fn call_without_input(
+        &self,
+        command: &str,
+        args: &Vec<String>,
+        context: &CommandContext,
+    ) -> Result<(), SageError> {
         print_info(&format!(
             "Executing command: `terraform {} {}`",
             command, args.join(" ")
         ));
-        print_info(&format!("Terraform output: \n"));
-        let mut process = Command::new("terraform")
-            .arg(command)
-            .args(args)
+        print_info("Terraform output: \n");
+        let started = Instant::now();
+        let mut process = self
+            .get_process(command, args, context)
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
             .spawn()
             .context(command)?;
 
-        process.wait().context("spawn process")?;
-        Ok(())
+        let status = process.wait().context("spawn process")?;
+        record_invocation(command, args, context, status.code(), started.elapsed());
+        self.check_exit_status(command, status)
     }
 
     // Invokes Terraform's command with the given `command` name and `args` arguments.
     // Before execution capture all stdout/stderr output and prints in user's terminal, then
     // asks for a user's input for the command, execute the command if acceptable and output
     // the execution result.
-    pub fn call_with_input(&self, command: &str, args: &Vec<String>) -> Result<(), SageError> {
+    pub fn call_with_input(
+        &self,
+        command: &str,
+        args: &Vec<String>,
+        context: &CommandContext,
+    ) -> Result<(), SageError> {
         print_info(&format!(
             "Executing command: `terraform {} {}`",
             command, args.join(" ")
         ));
-        print_info(&format!("Terraform output: \n"));
-        let mut process = Command::new("terraform")
-            .arg(command)
-            .args(args)
+        print_info("Terraform output: \n");
+        let started = Instant::now();
+        let mut process = self
+            .get_process(command, args, context)
             .stdin(Stdio::inherit())
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
             .spawn()
             .context(command)?;
 
-        process.wait().context("spawn process")?;
-        Ok(())
+        let status = process.wait().context("spawn process")?;
+        record_invocation(command, args, context, status.code(), started.elapsed());
+        self.check_exit_status(command, status)
+    }
+
+    // Returns an error when Terraform's command has exited with the non-zero code.
+    fn check_exit_status(&self, command: &str, status: ExitStatus) -> Result<(), SageError> {
+        match (status.success(), status.code()) {
+            (true, _) => Ok(()),
+            (false, Some(code)) => {
+                let message = format!("exited with code {}", code);
+                Err(SageError::TerraformFailed(
+                    command.to_string(),
+                    Some(code),
+                    message,
+                ))
+            }
+            (false, None) => {
+                let message = String::from("terminated by signal");
+                Err(SageError::TerraformFailed(
+                    command.to_string(),
+                    None,
+                    message,
+                ))
+            }
+        }
+    }
+
+    // Invokes Terraform's command with the given `command` name and `args` arguments.
+    // Returns the captured output of the command, or an error with the captured
+    // errors when the command has failed.
+    pub fn call_with_output(
+        &self,
+        command: &str,
+        args: &Vec<String>,
+        context: &CommandContext,
+    ) -> Result<String, SageError> {
+        let started = Instant::now();
+        let output = self
+            .get_process(command, args, context)
+            .stdin(Stdio::null())
+            .output()
+            .context(command)?;
+        let exit_code = output.status.code();
+        record_invocation(command, args, context, exit_code, started.elapsed());
+
+        match output.status.success() {
+            true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+            false => {
+                let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+                Err(SageError::TerraformFailed(
+                    command.to_string(),
+                    exit_code,
+                    message,
+                ))
+            }
+        }
+    }
+
+    // Invokes Terraform's command like `call_with_output`, but treats the exit
+    // codes listed in `accepted_codes` as successful. Returns the exit code of
+    // the command, the captured output is dropped.
+    pub fn call_with_exit_code(
+        &self,
+        command: &str,
+        args: &Vec<String>,
+        context: &CommandContext,
+        accepted_codes: &[i32],
+    ) -> Result<i32, SageError> {
+        let started = Instant::now();
+        let output = self
+            .get_process(command, args, context)
+            .stdin(Stdio::null())
+            .output()
+            .context(command)?;
+        let exit_code = output.status.code();
+        record_invocation(command, args, context, exit_code, started.elapsed());
+
+        match exit_code {
+            Some(code) if accepted_codes.contains(&code) => Ok(code),
+            _ => {
+                let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+                Err(SageError::TerraformFailed(
+                    command.to_string(),
+                    exit_code,
+                    message,
+                ))
+            }
+        }
     }
 
     // Deletes the file with the given path specified in `filepath` parameter.
+    // Files that weren't generated by terraform-sage are left untouched.
     pub fn delete_main_tf(&self, filepath: &String) -> Result<(), SageError> {
+        if !is_generated_file(filepath) {
+            print_warning(&format!(
+                "The {} file wasn't generated by terraform-sage and will not be deleted.",
+                filepath
+            ));
+            return Ok(());
+        }
+
         let delete_message = format!("Deleting {} file after execution...", filepath);
         print_warning(&delete_message);
         fs::remove_file(filepath).context(filepath)?;
+
+        let mut manifest = Manifest::load_for(filepath);
+        if manifest.remove(filepath).is_some() {
+            manifest.save()?;
+        }
         Ok(())
     }
 }
+
+// Parses the version from the output of the `terraform version` command,
+// e.g. "Terraform v0.15.4".
+fn parse_version(output: &str) -> Option<(u32, u32, u32)> {
+    let version = output.lines().next()?.trim().strip_prefix("Terraform v")?;
+    let mut numbers = version
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|number| number.parse::<u32>().ok());
+    Some((numbers.next()??, numbers.next()??, numbers.next()??))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::project::ProjectConfig;
+    use crate::terraform::{
+        parse_version, CommandContext, TerraformClient, DATA_DIRECTORY_VARIABLE,
+    };
+    use crate::utils::{create_test_directory, get_data_directory};
+
+    #[test]
+    fn test_get_process_runs_each_stack_in_its_directory() {
+        let directory = create_test_directory("process-stacks");
+        let content = "[stacks.network]\n\n[stacks.app]\ndepends_on = [\"network\"]\n";
+        fs::write(directory.join("sage.toml"), content).unwrap();
+        fs::create_dir_all(directory.join("network")).unwrap();
+        fs::create_dir_all(directory.join("app")).unwrap();
+        let content = "[terraform]\ndata_per_environment = true\n";
+        fs::write(directory.join("app").join("sage.toml"), content).unwrap();
+
+        let directory = directory.to_string_lossy().into_owned();
+        let project = ProjectConfig::load(&directory).unwrap();
+        let config = String::from("dev");
+        let terraform = TerraformClient::new();
+        let processes: Vec<(String, _)> = project
+            .get_stacks_order(&String::from("all"))
+            .unwrap()
+            .iter()
+            .map(|stack| {
+                let stack_directory = project.get_stack_directory(&directory, stack);
+                let context = CommandContext::new(&stack_directory, &config, None).unwrap();
+                let process = terraform.get_process("plan", &vec![], &context);
+                let current_directory = process.get_current_dir().map(Path::to_path_buf);
+                let data_directory = process
+                    .get_envs()
+                    .find(|(name, _value)| *name == DATA_DIRECTORY_VARIABLE)
+                    .and_then(|(_name, value)| value.map(|value| value.to_os_string()));
+                (stack_directory, (current_directory, data_directory))
+            })
+            .collect();
+        let app_data_directory = get_data_directory(&processes[1].0, &config);
+        fs::remove_dir_all(&directory).unwrap();
+
+        let (network_directory, (network_cwd, network_data)) = &processes[0];
+        assert_eq!(network_cwd.as_deref(), Some(Path::new(network_directory)));
+        assert_eq!(network_data, &None);
+        let (app_directory, (app_cwd, app_data)) = &processes[1];
+        assert_eq!(app_cwd.as_deref(), Some(Path::new(app_directory)));
+        assert_eq!(app_data, &Some(app_data_directory.into()));
+    }
+
+    #[test]
+    fn test_parse_version() {
+        let output = "Terraform v1.11.4\non linux_amd64\n";
+        assert_eq!(parse_version(output), Some((1, 11, 4)));
+        assert_eq!(parse_version("Terraform v0.12.31-beta1"), Some((0, 12, 31)));
+        assert_eq!(parse_version("terraform: command not found"), None);
+    }
+}
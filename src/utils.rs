@@ -1,13 +1,27 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::ffi::OsStr;
 use std::fs::{self, DirEntry};
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use quick_error::ResultExt;
+use sha2::{Digest, Sha256};
+use toml::Value as TomlValue;
 
 use crate::error::SageError;
+use crate::manifest::SAGE_DIRECTORY_NAME;
+use crate::terraform::TERRAFORM_EXTENSIONS;
 
 pub const CONFIG_DIRECTORY_NAME: &'static str = "configs";
+pub const DATA_DIRECTORY_NAME: &'static str = "data";
+pub const PLANS_DIRECTORY_NAME: &'static str = "plans";
+pub const CONFIG_NAME_SEPARATOR: &'static str = "/";
+
+// Number of the temporary files created by the process, used to make their
+// names unique across the threads.
+static TEMP_FILES_COUNT: AtomicUsize = AtomicUsize::new(0);
 
 // Returns file extension.
 pub fn get_extension_from_filename(filename: &str) -> &str {
@@ -16,6 +30,110 @@ pub fn get_extension_from_filename(filename: &str) -> &str {
         .map_or("", |ext| OsStr::to_str(ext).unwrap_or(""))
 }
 
+// Returns SHA-256 hash of the given content in the hexadecimal form.
+pub fn get_content_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+// Writes the content to the file by path in `path` parameter. The content
+// is written in a temporary file in the same directory first and then the
+// temporary file is renamed, so that the target file is never left partially
+// written. The permissions of the existing file are preserved. If the file
+// already has the same content, then it stays untouched and returns `false`.
+pub fn write_file_atomically(path: &String, content: &str) -> Result<bool, SageError> {
+    let target = Path::new(path);
+    if let Ok(current) = fs::read(target) {
+        if current == content.as_bytes() {
+            return Ok(false);
+        }
+    }
+
+    let file_name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp_path = target.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        file_name,
+        process::id(),
+        TEMP_FILES_COUNT.fetch_add(1, Ordering::SeqCst)
+    ));
+
+    match write_temp_file(target, &temp_path, content) {
+        Ok(_) => Ok(true),
+        Err(err) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(err)
+        }
+    }
+}
+
+// Writes the content in the temporary file and moves it to the target path.
+fn write_temp_file(target: &Path, temp_path: &PathBuf, content: &str) -> Result<(), SageError> {
+    let temp_filename = temp_path.to_string_lossy().into_owned();
+    let mut file = fs::File::create(temp_path).context(&temp_filename)?;
+    file.write_all(content.as_bytes()).context(&temp_filename)?;
+    file.sync_all().context(&temp_filename)?;
+
+    if let Ok(metadata) = fs::metadata(target) {
+        fs::set_permissions(temp_path, metadata.permissions()).context(&temp_filename)?;
+    }
+
+    let target_filename = target.to_string_lossy().into_owned();
+    fs::rename(temp_path, target).context(&target_filename)?;
+    Ok(())
+}
+
+// Parses the content of the TOML file by path in `path` parameter. Parse errors
+// are converted by `get_error` into the error with the position of the invalid
+// definition in the file.
+pub fn parse_toml_file(
+    path: &String,
+    raw_data: &str,
+    get_error: fn(String, usize, usize, String) -> SageError,
+) -> Result<TomlValue, SageError> {
+    raw_data.parse::<TomlValue>().map_err(|err| {
+        let description = err.to_string();
+        match err.line_col() {
+            Some((line, column)) => {
+                let position = format!(" at line {} column {}", line + 1, column + 1);
+                let message = description.strip_suffix(&position).unwrap_or(&description);
+                get_error(path.clone(), line + 1, column + 1, message.to_string())
+            }
+            None => {
+                let message = format!("The {} file is invalid: {}.", path, description);
+                SageError::InvalidConfig(message)
+            }
+        }
+    })
+}
+
+// Returns sorted names of the files that differ in two given directories:
+// the files that exist only in one of them or have different content. Hidden
+// files and nested directories are not compared.
+pub fn get_different_files(left: &Path, right: &Path) -> Vec<String> {
+    let get_file_names = |directory: &Path| -> BTreeSet<String> {
+        fs::read_dir(directory)
+            .map(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .filter(|f| f.path().is_file())
+                    .map(|f| f.file_name().to_string_lossy().into_owned())
+                    .filter(|name| !name.starts_with('.'))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let left_files = get_file_names(left);
+    let right_files = get_file_names(right);
+    left_files
+        .union(&right_files)
+        .filter(|name| fs::read(left.join(name)).ok() != fs::read(right.join(name)).ok())
+        .cloned()
+        .collect()
+}
+
 // Returns list of files for directory specified in `path` parameter.
 pub fn get_files_list(path: &String) -> Result<Vec<DirEntry>, SageError> {
     let files: Vec<_> = fs::read_dir(path)
@@ -28,7 +146,9 @@ pub fn get_files_list(path: &String) -> Result<Vec<DirEntry>, SageError> {
 
 // Returns dictionary, where the key is configuration name and value is the
 // full path to this directory. Search is happening by directory specified
-// in `path` parameter.
+// in `path` parameter. Configurations are the innermost directories inside
+// of the `configs` directory, so the nested ones are named by their relative
+// path, e.g. `eu-west-1/production`.
 pub fn get_configs(path: &String) -> Result<HashMap<String, String>, SageError> {
     let configs = get_files_list(path)?
         .into_iter()
@@ -37,26 +157,106 @@ pub fn get_configs(path: &String) -> Result<HashMap<String, String>, SageError>
             Err(_) => false,
         })
         .filter(|f| f.file_name() == CONFIG_DIRECTORY_NAME)
-        .flat_map(|f| {
-            let dir = f.path().to_string_lossy().into_owned();
-            match get_files_list(&dir) {
-                Ok(vec) => vec,
-                Err(_) => vec![],
+        .flat_map(|f| get_leaf_directories(&f.path().to_string_lossy().into_owned()))
+        .collect();
+
+    Ok(configs)
+}
+
+// Returns dictionary, where the key is the relative path to the directory
+// without nested directories and value is the full path to it. Search is
+// happening recursively by directory specified in `path` parameter. Only the
+// nested directories with Terraform files make a directory the parent, so the
+// other ones (e.g. with data files) are left to the directory itself. Hidden
+// directories are ignored.
+pub fn get_leaf_directories(path: &String) -> HashMap<String, String> {
+    let mut directories = HashMap::new();
+    let mut pending = vec![(String::new(), path.clone())];
+    while let Some((prefix, directory)) = pending.pop() {
+        for entry_path in get_subdirectories(&directory) {
+            let file_name = Path::new(&entry_path)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let name = match prefix.is_empty() {
+                true => file_name,
+                false => format!("{}{}{}", prefix, CONFIG_NAME_SEPARATOR, file_name),
+            };
+
+            let is_parent = get_subdirectories(&entry_path)
+                .iter()
+                .any(has_terraform_files);
+            match is_parent {
+                true => pending.push((name, entry_path)),
+                false if prefix.is_empty() || has_terraform_files(&entry_path) => {
+                    directories.insert(name, entry_path);
+                }
+                false => {}
             }
-        })
+        }
+    }
+    directories
+}
+
+// Returns the paths to the directories in the directory specified in
+// `directory` parameter. Hidden directories are ignored.
+fn get_subdirectories(directory: &String) -> Vec<String> {
+    get_files_list(directory)
+        .unwrap_or_default()
+        .into_iter()
         .filter(|f| match f.metadata() {
             Ok(metadata) => metadata.is_dir(),
             Err(_) => false,
         })
-        .map(|dir| {
-            (
-                dir.file_name().to_string_lossy().into_owned(), // directory name
-                dir.path().to_string_lossy().into_owned(),      // path
-            )
+        .filter(|f| !f.file_name().to_string_lossy().starts_with('.'))
+        .map(|f| f.path().to_string_lossy().into_owned())
+        .collect()
+}
+
+// Checks that the directory specified in `directory` parameter or any of its
+// nested directories contains Terraform files.
+fn has_terraform_files(directory: &String) -> bool {
+    let has_files = get_files_list(directory)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|f| {
+            f.metadata()
+                .map(|metadata| metadata.is_file())
+                .unwrap_or(false)
         })
-        .collect();
+        .any(|f| {
+            let file_name = f.file_name().to_string_lossy().into_owned();
+            TERRAFORM_EXTENSIONS.contains(&get_extension_from_filename(&file_name))
+        });
+    has_files
+        || get_subdirectories(directory)
+            .iter()
+            .any(has_terraform_files)
+}
 
-    Ok(configs)
+// Returns the absolute path to the Terraform data directory of the configuration
+// with `config` name, so that each configuration keeps its own initialized
+// backend and providers in the `.sage/data/<config>` directory.
+pub fn get_data_directory(directory: &String, config: &String) -> String {
+    fs::canonicalize(directory)
+        .unwrap_or_else(|_| PathBuf::from(directory))
+        .join(SAGE_DIRECTORY_NAME)
+        .join(DATA_DIRECTORY_NAME)
+        .join(config)
+        .to_string_lossy()
+        .into_owned()
+}
+
+// Returns the absolute path to the file, where the plan of the configuration
+// with `config` name is saved before applying.
+pub fn get_plan_file(directory: &String, config: &String) -> String {
+    fs::canonicalize(directory)
+        .unwrap_or_else(|_| PathBuf::from(directory))
+        .join(SAGE_DIRECTORY_NAME)
+        .join(PLANS_DIRECTORY_NAME)
+        .join(format!("{}.tfplan", config))
+        .to_string_lossy()
+        .into_owned()
 }
 
 // Checks that the given name is represented in the configurations list.
@@ -64,15 +264,62 @@ pub fn is_correct_config(name: &String, configs: HashMap<String, String>) -> Res
     match configs.contains_key(name) {
         true => Ok(()),
         false => {
-            let message = String::from(format!("Configuration with {} name was not found.", name));
+            let message = format!("Configuration with {} name was not found.", name);
             Err(SageError::InvalidConfig(message))
         }
     }
 }
 
+// Checks that the value matches the pattern, where `*` stands for any sequence
+// of characters and `?` stands for any single character.
+pub fn matches_pattern(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((star, position)) => {
+                    p = star + 1;
+                    v = position + 1;
+                    backtrack = Some((star, position + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Creates an empty directory with `name` for the test in the temporary
+// directory. The name includes the process id, so that the files of the
+// tests are never shared with another run.
+#[cfg(test)]
+pub fn create_test_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("terraform-sage-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::utils::{get_configs, get_files_list, is_correct_config};
+    use std::fs;
+
+    use crate::utils::{
+        create_test_directory, get_configs, get_different_files, get_files_list, is_correct_config,
+        matches_pattern, write_file_atomically,
+    };
 
     #[test]
     fn test_get_files_list_returns_vector_of_entries() {
@@ -123,6 +370,53 @@ mod tests {
         assert_eq!(result.is_err(), true);
     }
 
+    #[test]
+    fn test_get_configs_returns_nested_configurations() {
+        let directory = create_test_directory("nested-configs");
+        fs::create_dir_all(directory.join("configs/dev")).unwrap();
+        fs::create_dir_all(directory.join("configs/eu-west-1/prod")).unwrap();
+        fs::create_dir_all(directory.join("configs/eu-west-1/staging")).unwrap();
+        fs::create_dir_all(directory.join("configs/.hidden")).unwrap();
+        for config in &["dev", "eu-west-1/prod", "eu-west-1/staging"] {
+            fs::write(
+                directory.join("configs").join(config).join("vars.tfvars"),
+                "",
+            )
+            .unwrap();
+        }
+
+        let path = directory.to_string_lossy().into_owned();
+        let configs = get_configs(&path).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let mut names: Vec<&String> = configs.keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["dev", "eu-west-1/prod", "eu-west-1/staging"]);
+        assert_eq!(
+            configs["eu-west-1/prod"].ends_with("configs/eu-west-1/prod"),
+            true
+        );
+    }
+
+    #[test]
+    fn test_get_configs_keeps_configurations_with_data_directories() {
+        let directory = create_test_directory("data-directory-configs");
+        fs::create_dir_all(directory.join("configs/prod/files")).unwrap();
+        fs::write(directory.join("configs/prod/vars.tfvars"), "").unwrap();
+        fs::write(directory.join("configs/prod/files/data.json"), "{}").unwrap();
+        fs::create_dir_all(directory.join("configs/eu/dev/files")).unwrap();
+        fs::write(directory.join("configs/eu/dev/vars.tfvars"), "").unwrap();
+        fs::create_dir_all(directory.join("configs/eu/files")).unwrap();
+
+        let path = directory.to_string_lossy().into_owned();
+        let configs = get_configs(&path).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let mut names: Vec<&String> = configs.keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["eu/dev", "prod"]);
+    }
+
     #[test]
     fn test_is_correct_config_return_empty_result() {
         let path = String::from("./examples/approach_two");
@@ -140,4 +434,67 @@ mod tests {
 
         assert_eq!(result.is_err(), true);
     }
+
+    #[test]
+    fn test_write_file_atomically_skips_unchanged_content() {
+        let directory = create_test_directory("atomic");
+        let path = directory.join("main.tf").to_string_lossy().into_owned();
+
+        assert_eq!(write_file_atomically(&path, "a = 1").unwrap(), true);
+        assert_eq!(write_file_atomically(&path, "a = 1").unwrap(), false);
+        assert_eq!(write_file_atomically(&path, "a = 2").unwrap(), true);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a = 2");
+        let files_count = fs::read_dir(&directory).unwrap().count();
+        fs::remove_dir_all(directory).unwrap();
+
+        assert_eq!(files_count, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_file_atomically_preserves_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = create_test_directory("permissions");
+        let path = directory.join("main.tf").to_string_lossy().into_owned();
+        fs::write(&path, "a = 1").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        write_file_atomically(&path, "a = 2").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        fs::remove_dir_all(directory).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_get_different_files() {
+        let left = create_test_directory("diff-left");
+        let right = create_test_directory("diff-right");
+        fs::write(left.join("main.tf"), "a = 1").unwrap();
+        fs::write(right.join("main.tf"), "a = 2").unwrap();
+        fs::write(left.join("variables.tfvars"), "b = 1").unwrap();
+        fs::write(right.join("variables.tfvars"), "b = 1").unwrap();
+        fs::write(right.join("secrets.tfvars"), "c = 1").unwrap();
+
+        let files = get_different_files(&left, &right);
+        fs::remove_dir_all(&left).unwrap();
+        fs::remove_dir_all(&right).unwrap();
+
+        assert_eq!(files, vec!["main.tf", "secrets.tfvars"]);
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert_eq!(matches_pattern("aws_db_*", "aws_db_instance"), true);
+        assert_eq!(
+            matches_pattern(
+                "module.*.aws_instance.web[?]",
+                "module.app.aws_instance.web[1]"
+            ),
+            true
+        );
+        assert_eq!(matches_pattern("*prod*", "eu-west-1/production"), true);
+        assert_eq!(matches_pattern("aws_db_*", "aws_s3_bucket"), false);
+        assert_eq!(matches_pattern("production", "production/eu"), false);
+    }
 }
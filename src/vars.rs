@@ -0,0 +1,431 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use quick_error::ResultExt;
+use serde_json::Value as JsonValue;
+
+use crate::error::SageError;
+use crate::hcl::{parse_hcl, HclItem, HclValue};
+use crate::template::{
+    get_template_context, is_generated_file, render_from_template, BUILTIN_CONTEXT_SOURCE,
+    SECRET_VALUE_MASK,
+};
+use crate::utils::{get_extension_from_filename, get_files_list};
+
+pub const VARIABLE_BLOCK_NAME: &'static str = "variable";
+pub const AUTO_VARIABLES_FILE_NAME: &'static str = "terraform.tfvars";
+pub const AUTO_VARIABLES_FILE_SUFFIX: &'static str = ".auto.tfvars";
+pub const VARIABLE_ENV_PREFIX: &'static str = "TF_VAR_";
+
+// Type constraint of the declared variable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VariableType {
+    String,
+    Number,
+    Bool,
+    List,
+    Map,
+    Any,
+}
+
+impl fmt::Display for VariableType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            VariableType::String => "string",
+            VariableType::Number => "number",
+            VariableType::Bool => "bool",
+            VariableType::List => "list",
+            VariableType::Map => "map",
+            VariableType::Any => "any",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Result of comparing the values of two configurations.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueDifference {
+    Identical,
+    Different,
+    OnlyLeft,
+    OnlyRight,
+}
+
+// Variable declared in the `variable` block of the Terraform module.
+#[derive(Debug, Clone)]
+pub struct VariableDefinition {
+    pub name: String,
+    pub variable_type: Option<VariableType>,
+    pub required: bool,
+    pub filename: String,
+}
+
+// Value assigned to the variable in the variables file.
+#[derive(Debug, Clone)]
+pub struct VariableAssignment {
+    pub name: String,
+    pub value: HclValue,
+    pub filename: String,
+}
+
+// Problems found in the variable assignments of the configuration.
+#[derive(Debug, Default)]
+pub struct VariablesReport {
+    // Required variables without assigned value.
+    pub missing: Vec<String>,
+    // Assignments to the undeclared variables with the file name.
+    pub unknown: Vec<(String, String)>,
+    // Assignments with the value of wrong type with the expected type and the file name.
+    pub mismatched: Vec<(String, VariableType, String)>,
+}
+
+impl VariablesReport {
+    // Returns the total number of found problems.
+    pub fn problems_count(&self) -> usize {
+        self.missing.len() + self.unknown.len() + self.mismatched.len()
+    }
+}
+
+// Returns the variables declared in the Terraform modules of the project,
+// located in the directory specified in `directory` parameter, and in the
+// template rendered for the configuration with `config` name. The modules
+// generated by terraform-sage are skipped in favor of the template.
+pub fn get_variable_definitions(
+    directory: &String,
+    config: &String,
+    template: &String,
+) -> Result<BTreeMap<String, VariableDefinition>, SageError> {
+    let mut sources = Vec::new();
+
+    let mut modules: Vec<String> = get_files_list(directory)?
+        .into_iter()
+        .map(|f| f.path().to_string_lossy().into_owned())
+        .filter(|path| get_extension_from_filename(path) == "tf")
+        .filter(|path| !is_generated_file(path))
+        .collect();
+    modules.sort();
+    for module in modules {
+        let content = fs::read_to_string(&module).context(&module)?;
+        sources.push((module, content));
+    }
+
+    let path_to_template = Path::new(directory)
+        .join(template)
+        .to_string_lossy()
+        .into_owned();
+    if Path::new(&path_to_template).exists() {
+        let content = render_from_template(directory, config, &path_to_template, false)?;
+        sources.push((path_to_template, content));
+    }
+
+    let mut definitions = BTreeMap::new();
+    for (filename, content) in sources {
+        parse_hcl(&filename, &content)?
+            .into_iter()
+            .filter_map(|item| match item {
+                HclItem::Block(name, labels, body) if name == VARIABLE_BLOCK_NAME => {
+                    labels.first().map(|label| (label.clone(), body))
+                }
+                _ => None,
+            })
+            .for_each(|(name, body)| {
+                let mut definition = VariableDefinition {
+                    name: name.clone(),
+                    variable_type: None,
+                    required: true,
+                    filename: filename.clone(),
+                };
+                body.iter().for_each(|item| match item {
+                    HclItem::Attribute(key, value) if key == "type" => {
+                        definition.variable_type = parse_variable_type(value)
+                    }
+                    HclItem::Attribute(key, _value) if key == "default" => {
+                        definition.required = false
+                    }
+                    _ => {}
+                });
+                definitions.insert(name, definition);
+            });
+    }
+
+    Ok(definitions)
+}
+
+// Returns the variable files, automatically loaded by Terraform from the
+// directory specified in `directory` parameter.
+pub fn get_auto_variable_files(directory: &String) -> Vec<String> {
+    let mut files: Vec<String> = get_files_list(directory)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|f| {
+            let file_name = f.file_name().to_string_lossy().into_owned();
+            file_name == AUTO_VARIABLES_FILE_NAME || file_name.ends_with(AUTO_VARIABLES_FILE_SUFFIX)
+        })
+        .map(|f| f.path().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    files
+}
+
+// Returns the variable assignments from the given variable files. Blocks
+// in the files are ignored.
+pub fn get_variable_assignments(files: &Vec<String>) -> Result<Vec<VariableAssignment>, SageError> {
+    let mut assignments = Vec::new();
+
+    for filename in files {
+        let content = fs::read_to_string(filename).context(filename)?;
+        parse_hcl(filename, &content)?.into_iter().for_each(|item| {
+            if let HclItem::Attribute(name, value) = item {
+                assignments.push(VariableAssignment {
+                    name,
+                    value,
+                    filename: filename.clone(),
+                });
+            }
+        });
+    }
+
+    Ok(assignments)
+}
+
+// Checks the variable assignments against the declared variables. Required
+// variables passed via `TF_VAR_*` environment variables aren't reported.
+pub fn check_variables(
+    definitions: &BTreeMap<String, VariableDefinition>,
+    assignments: &Vec<VariableAssignment>,
+) -> VariablesReport {
+    let missing = definitions
+        .values()
+        .filter(|definition| definition.required)
+        .filter(|definition| !assignments.iter().any(|a| a.name == definition.name))
+        .filter(|definition| {
+            env::var(format!("{}{}", VARIABLE_ENV_PREFIX, definition.name)).is_err()
+        })
+        .map(|definition| definition.name.clone())
+        .collect();
+    let mut report = VariablesReport {
+        missing,
+        ..VariablesReport::default()
+    };
+
+    assignments
+        .iter()
+        .for_each(|assignment| match definitions.get(&assignment.name) {
+            None => report
+                .unknown
+                .push((assignment.name.clone(), assignment.filename.clone())),
+            Some(definition) => {
+                if let Some(variable_type) = &definition.variable_type {
+                    if !is_matching_type(variable_type, &assignment.value) {
+                        report.mismatched.push((
+                            assignment.name.clone(),
+                            variable_type.clone(),
+                            assignment.filename.clone(),
+                        ));
+                    }
+                }
+            }
+        });
+
+    report
+}
+
+// Returns the values of the variables assigned in the given variable files
+// and the keys of the template context for the configuration with `config`
+// name. Variables are prefixed with `var.` and the context keys with
+// `context.`, built-in context keys are skipped and secrets are masked.
+pub fn get_configuration_values(
+    directory: &String,
+    config: &String,
+    variable_files: &Vec<String>,
+) -> Result<BTreeMap<String, String>, SageError> {
+    let mut values = BTreeMap::new();
+
+    get_variable_assignments(variable_files)?
+        .into_iter()
+        .for_each(|assignment| {
+            let key = format!("var.{}", assignment.name);
+            values.insert(key, assignment.value.to_string());
+        });
+
+    get_template_context(directory, config)?
+        .into_iter()
+        .filter(|(_key, entry)| entry.source != BUILTIN_CONTEXT_SOURCE)
+        .for_each(|(key, entry)| {
+            let value = match (entry.reference, entry.secret) {
+                (Some(reference), _) => {
+                    format!("<output {} of {}>", reference.output, reference.stack)
+                }
+                (None, true) => SECRET_VALUE_MASK.to_string(),
+                (None, false) => match entry.value {
+                    JsonValue::String(value) => format!("{:?}", value),
+                    value => value.to_string(),
+                },
+            };
+            values.insert(format!("context.{}", key), value);
+        });
+
+    Ok(values)
+}
+
+// Compares the values of two configurations and returns the rows with the
+// key, the left and the right values and the difference between them.
+pub fn compare_values(
+    left: &BTreeMap<String, String>,
+    right: &BTreeMap<String, String>,
+) -> Vec<(String, Option<String>, Option<String>, ValueDifference)> {
+    let keys: BTreeSet<&String> = left.keys().chain(right.keys()).collect();
+
+    keys.into_iter()
+        .map(|key| {
+            let left_value = left.get(key).cloned();
+            let right_value = right.get(key).cloned();
+            let difference = match (&left_value, &right_value) {
+                (Some(left_value), Some(right_value)) if left_value == right_value => {
+                    ValueDifference::Identical
+                }
+                (Some(_), Some(_)) => ValueDifference::Different,
+                (Some(_), None) => ValueDifference::OnlyLeft,
+                _ => ValueDifference::OnlyRight,
+            };
+            (key.clone(), left_value, right_value, difference)
+        })
+        .collect()
+}
+
+// Converts the value of the `type` attribute into the variable type. Supports
+// both the quoted types of Terraform 0.11 and the type constraints of 0.12+.
+fn parse_variable_type(value: &HclValue) -> Option<VariableType> {
+    let raw = match value {
+        HclValue::String(raw) | HclValue::Expression(raw) => raw,
+        _ => return None,
+    };
+
+    match raw.split('(').next().unwrap_or_default().trim() {
+        "string" => Some(VariableType::String),
+        "number" => Some(VariableType::Number),
+        "bool" => Some(VariableType::Bool),
+        "list" | "set" | "tuple" => Some(VariableType::List),
+        "map" | "object" => Some(VariableType::Map),
+        "any" => Some(VariableType::Any),
+        _ => None,
+    }
+}
+
+// Checks that the value can be converted by Terraform to the given type.
+// Values that aren't literals can't be checked and always match.
+fn is_matching_type(variable_type: &VariableType, value: &HclValue) -> bool {
+    match (variable_type, value) {
+        (VariableType::Any, _) | (_, HclValue::Null) | (_, HclValue::Expression(_)) => true,
+        (VariableType::String, HclValue::String(_))
+        | (VariableType::String, HclValue::Number(_))
+        | (VariableType::String, HclValue::Bool(_)) => true,
+        (VariableType::Number, HclValue::Number(_)) => true,
+        (VariableType::Number, HclValue::String(raw)) => raw.parse::<f64>().is_ok(),
+        (VariableType::Bool, HclValue::Bool(_)) => true,
+        (VariableType::Bool, HclValue::String(raw)) => raw == "true" || raw == "false",
+        (VariableType::List, HclValue::List(_)) => true,
+        (VariableType::Map, HclValue::Map(_)) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::hcl::HclValue;
+    use crate::vars::{
+        check_variables, compare_values, get_configuration_values, get_variable_definitions,
+        ValueDifference, VariableAssignment, VariableDefinition, VariableType,
+    };
+
+    fn get_assignment(name: &str, value: HclValue) -> VariableAssignment {
+        VariableAssignment {
+            name: name.to_string(),
+            value,
+            filename: String::from("variables.tfvars"),
+        }
+    }
+
+    #[test]
+    fn test_get_variable_definitions() {
+        let directory = String::from("./examples/context_switch");
+        let config = String::from("dev");
+        let template = String::from("main.tpl");
+        let definitions = get_variable_definitions(&directory, &config, &template).unwrap();
+
+        assert_eq!(definitions.len(), 1);
+        let definition = &definitions["environment"];
+        assert_eq!(definition.variable_type, Some(VariableType::String));
+        assert_eq!(definition.required, false);
+    }
+
+    #[test]
+    fn test_check_variables() {
+        let mut definitions = BTreeMap::new();
+        for (name, variable_type, required) in [
+            ("environment", VariableType::String, true),
+            ("instances", VariableType::Number, false),
+            ("zones", VariableType::List, false),
+            ("tags", VariableType::Map, true),
+        ] {
+            let definition = VariableDefinition {
+                name: name.to_string(),
+                variable_type: Some(variable_type),
+                required,
+                filename: String::from("variables.tf"),
+            };
+            definitions.insert(name.to_string(), definition);
+        }
+        let assignments = vec![
+            get_assignment("environment", HclValue::String("dev".to_string())),
+            get_assignment("instances", HclValue::String("two".to_string())),
+            get_assignment("zones", HclValue::List(vec![])),
+            get_assignment("enviroment", HclValue::String("dev".to_string())),
+        ];
+
+        let report = check_variables(&definitions, &assignments);
+        assert_eq!(report.missing, vec!["tags"]);
+        assert_eq!(report.unknown.len(), 1);
+        assert_eq!(report.unknown[0].0, "enviroment");
+        assert_eq!(report.mismatched.len(), 1);
+        assert_eq!(report.mismatched[0].0, "instances");
+        assert_eq!(report.mismatched[0].1, VariableType::Number);
+        assert_eq!(report.problems_count(), 3);
+    }
+
+    #[test]
+    fn test_compare_values() {
+        let directory = String::from("./examples/context_switch");
+        let get_values = |config: &str| {
+            let variable_files = vec![format!(
+                "./examples/context_switch/configs/{}/variables.tfvars",
+                config
+            )];
+            get_configuration_values(&directory, &config.to_string(), &variable_files).unwrap()
+        };
+        let mut dev = get_values("dev");
+        let staging = get_values("staging");
+        dev.insert("var.debug".to_string(), "true".to_string());
+
+        let rows = compare_values(&dev, &staging);
+        let differences: Vec<(&str, &ValueDifference)> = rows
+            .iter()
+            .map(|(key, _left, _right, difference)| (key.as_str(), difference))
+            .collect();
+        assert_eq!(
+            differences,
+            vec![
+                ("context.aws_bucket_name", &ValueDifference::Different),
+                ("context.profile", &ValueDifference::Different),
+                ("var.debug", &ValueDifference::OnlyLeft),
+                ("var.environment", &ValueDifference::Different),
+            ]
+        );
+        assert_eq!(rows[0].1, Some("\"dev-bucket\"".to_string()));
+    }
+}